@@ -57,7 +57,11 @@ fn main() {
             "Msg",
             "MsgContent",
             "Mention",
+            "MemberIds",
             "MsgRead",
+            "ReadCursor",
+            "ReadReceipt",
+            "GroupReadStatus",
             "MsgToDb",
             "GetDbMsgRequest",
             "GetDbMessagesRequest",
@@ -65,6 +69,7 @@ fn main() {
             "UserAndGroupID",
             "User",
             "UserUpdate",
+            "PushToken",
             "UserWithMatchType",
             "Friend",
             "FriendInfo",
@@ -96,6 +101,15 @@ fn main() {
             "Hangup",
             "AgreeSingleCall",
             "Candidate",
+            "KnockOff",
+            "KnockOffReason",
+            "Signal",
+            "SignalType",
+            "Presence",
+            "PresenceStatus",
+            "Connection",
+            "GatewayStats",
+            "SyncSeq",
         ])
         .with_sqlx_type(&["FriendshipStatus", "GroupMemberRole"])
         .compile(&["protos/messages.proto"], &["protos"])
@@ -1,7 +1,7 @@
 // db config
 // server config
 
-use crate::errors::Error;
+use crate::errors::{Error, ErrorKind};
 use serde::{Deserialize, Serialize};
 use std::{fs, path::Path};
 
@@ -13,6 +13,9 @@ pub struct Config {
     // server config
     pub server: ServerConfig,
     pub kafka: KafkaConfig,
+    /// the queue between the chat rpc service and the consumer
+    #[serde(default)]
+    pub message_bus: MessageBusKind,
     pub redis: RedisConfig,
     pub rpc: RpcConfig,
     pub websocket: WsServerConfig,
@@ -20,6 +23,9 @@ pub struct Config {
     pub oss: OssConfig,
     pub mail: MailConfig,
     pub log: LogConfig,
+    /// the push notifications of the offline users
+    #[serde(default)]
+    pub push: PushConfig,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -31,6 +37,16 @@ pub enum Component {
     All,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageBusKind {
+    #[default]
+    Kafka,
+    /// the channel in the process, the chat rpc service and the consumer run in the same process,
+    /// the messages not consumed are lost when the process exits
+    Memory,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct LogConfig {
     pub level: String,
@@ -49,6 +65,33 @@ impl LogConfig {
         }
     }
 }
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PushConfig {
+    #[serde(default)]
+    pub provider: PushProviderKind,
+    /// the url which receives the notifications by post of the webhook provider
+    #[serde(default)]
+    pub webhook_url: String,
+    /// milliseconds
+    #[serde(default = "default_push_timeout")]
+    pub timeout: u64,
+}
+
+fn default_push_timeout() -> u64 {
+    3000
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushProviderKind {
+    /// the offline users get nothing until they are online
+    #[default]
+    None,
+    Webhook,
+    /// log the notifications only, for development
+    Mock,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MailConfig {
     pub server: String,
@@ -106,6 +149,158 @@ pub struct WsServerConfig {
     pub port: u16,
     pub name: String,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub login_policy: LoginPolicy,
+    /// the capacity of each connection's outbound queue
+    #[serde(default = "default_outbound_queue_size")]
+    pub outbound_queue_size: usize,
+    /// milliseconds, the connection will be disconnected if its queue stays full longer than it
+    #[serde(default = "default_slow_consumer_timeout")]
+    pub slow_consumer_timeout: u64,
+    /// seconds, the connections are closed gradually in the window when the gateway is draining
+    #[serde(default = "default_drain_window")]
+    pub drain_window: u64,
+    /// the credential of the admin api, the admin api is disabled if it is not set or empty
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// the max count of un-acked messages kept for each connection
+    #[serde(default = "default_ack_window")]
+    pub ack_window: usize,
+    /// milliseconds, the first retry delay of an un-acked message, it doubles on each retry
+    #[serde(default = "default_ack_timeout")]
+    pub ack_timeout: u64,
+    /// give up the message after retried so many times, the client can pull it from inbox,
+    /// it can not be greater than MAX_ACK_RETRIES
+    #[serde(default = "default_ack_max_retries")]
+    pub ack_max_retries: u32,
+    /// the limits of the inbound messages
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// seconds, how often to send the ping to the client
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: u64,
+    /// the connection is closed if nothing is received from the client
+    /// for so many heartbeat intervals
+    #[serde(default = "default_max_missed_heartbeats")]
+    pub max_missed_heartbeats: u32,
+    /// the inbound messages are handled by the workers concurrently,
+    /// the messages of the same sender are handled by the same worker in order
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// seconds, the lease of the gateway in the routing registry, it is refreshed by the gateway
+    /// periodically, the connections of the gateway are ignored once it is expired
+    #[serde(default = "default_gateway_lease_ttl")]
+    pub gateway_lease_ttl: u64,
+}
+
+fn default_outbound_queue_size() -> usize {
+    128
+}
+
+fn default_slow_consumer_timeout() -> u64 {
+    5000
+}
+
+fn default_drain_window() -> u64 {
+    30
+}
+
+fn default_ack_window() -> usize {
+    256
+}
+
+fn default_ack_timeout() -> u64 {
+    3000
+}
+
+/// the upper bound of `ack_max_retries`, the delay of the last retry is capped anyway,
+/// more retries only keep the window occupied
+pub const MAX_ACK_RETRIES: u32 = 16;
+
+fn default_ack_max_retries() -> u32 {
+    5
+}
+
+fn default_heartbeat_interval() -> u64 {
+    30
+}
+
+fn default_max_missed_heartbeats() -> u32 {
+    3
+}
+
+fn default_workers() -> usize {
+    16
+}
+
+fn default_gateway_lease_ttl() -> u64 {
+    30
+}
+
+/// the limits of a category of inbound messages
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct RateLimit {
+    /// messages per second of each connection
+    pub rate: u32,
+    /// the max messages sent at once of each connection
+    pub burst: u32,
+    /// messages per second of each user, counted across all devices and gateways
+    pub user_rate: u32,
+}
+
+/// the inbound messages are limited by category,
+/// the frames over the limits are rejected with an error
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// chat messages and the other operations
+    pub chat: RateLimit,
+    /// typing signals and call signaling
+    pub signaling: RateLimit,
+    /// read receipts
+    pub read: RateLimit,
+    /// the connection exceeds the limits so many times in the window will be disconnected
+    pub max_violations: u32,
+    /// seconds
+    pub violation_window: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            chat: RateLimit {
+                rate: 10,
+                burst: 20,
+                user_rate: 30,
+            },
+            signaling: RateLimit {
+                rate: 5,
+                burst: 10,
+                user_rate: 15,
+            },
+            read: RateLimit {
+                rate: 10,
+                burst: 50,
+                user_rate: 50,
+            },
+            max_violations: 20,
+            violation_window: 60,
+        }
+    }
+}
+
+/// how many sessions a user can keep at the same time,
+/// the old sessions which are not allowed will be knocked off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginPolicy {
+    /// one session for each platform
+    SinglePlatform,
+    /// only one session in total
+    Single,
+    /// no limit, only the same device will be replaced
+    #[default]
+    Unlimited,
 }
 
 impl WsServerConfig {
@@ -126,6 +321,19 @@ impl WsServerConfig {
             format!("ws://{}:{}", self.host, self.port)
         }
     }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.ack_max_retries > MAX_ACK_RETRIES {
+            return Err(Error::with_details(
+                ErrorKind::ConfigParseError,
+                format!(
+                    "websocket.ack_max_retries {} is greater than {}",
+                    self.ack_max_retries, MAX_ACK_RETRIES
+                ),
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -211,12 +419,33 @@ impl RedisConfig {
 pub struct KafkaConfig {
     pub hosts: Vec<String>,
     pub topic: String,
+    /// the partitions of the topic when it is created,
+    /// the messages of the same receiver are keyed to the same partition
+    #[serde(default = "default_partitions")]
+    pub partitions: i32,
+    #[serde(default = "default_replication")]
+    pub replication: i32,
+    /// the messages failed to be handled are sent to it with the reason
+    #[serde(default = "default_dead_letter_topic")]
+    pub dead_letter_topic: String,
     pub connect_timeout: u16,
     pub group: String,
     pub producer: KafkaProducer,
     pub consumer: KafkaConsumer,
 }
 
+fn default_partitions() -> i32 {
+    1
+}
+
+fn default_replication() -> i32 {
+    1
+}
+
+fn default_dead_letter_topic() -> String {
+    "sandcat-chat-dlq".to_string()
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct KafkaProducer {
     pub timeout: u16,
@@ -229,6 +458,41 @@ pub struct KafkaProducer {
 pub struct KafkaConsumer {
     pub session_timeout: u16,
     pub auto_offset_reset: String,
+    /// the retries of the retryable errors before dead lettering the message
+    #[serde(default = "default_consumer_max_retries")]
+    pub max_retries: u32,
+    /// milliseconds, the backoff of the first retry, it is doubled for each retry
+    #[serde(default = "default_consumer_retry_backoff")]
+    pub retry_backoff: u64,
+    /// milliseconds
+    #[serde(default = "default_consumer_max_retry_backoff")]
+    pub max_retry_backoff: u64,
+    /// the max records handled as a batch
+    #[serde(default = "default_consumer_batch_size")]
+    pub batch_size: usize,
+    /// milliseconds, wait for more records after the first record of a batch
+    #[serde(default = "default_consumer_batch_timeout")]
+    pub batch_timeout: u64,
+}
+
+fn default_consumer_max_retries() -> u32 {
+    5
+}
+
+fn default_consumer_retry_backoff() -> u64 {
+    200
+}
+
+fn default_consumer_max_retry_backoff() -> u64 {
+    10000
+}
+
+fn default_consumer_batch_size() -> usize {
+    100
+}
+
+fn default_consumer_batch_timeout() -> u64 {
+    50
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -291,7 +555,9 @@ pub struct OAuth2Item {
 impl Config {
     pub fn load(filename: impl AsRef<Path>) -> Result<Self, Error> {
         let content = fs::read_to_string(filename)?;
-        Ok(serde_yaml::from_str(&content)?)
+        let config: Self = serde_yaml::from_str(&content)?;
+        config.websocket.validate()?;
+        Ok(config)
     }
 }
 
@@ -173,6 +173,23 @@ impl Error {
     pub fn path_parsing(err: impl StdError + 'static + Send + Sync) -> Self {
         Self::new(ErrorKind::PathParsing, err.to_string(), err)
     }
+
+    /// the errors of the storage, cache and network may be gone by retrying,
+    /// the others like parsing errors happen again
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind,
+            ErrorKind::UnknownError
+                | ErrorKind::DbError
+                | ErrorKind::BroadCastError
+                | ErrorKind::TonicError
+                | ErrorKind::MongoDbOperateError
+                | ErrorKind::RedisError
+                | ErrorKind::IOError
+                | ErrorKind::ReqwestError
+                | ErrorKind::ServiceNotFound
+        )
+    }
 }
 
 impl fmt::Display for Error {
@@ -385,3 +402,38 @@ impl From<bincode::Error> for Error {
         Self::new(ErrorKind::BinCode, value.to_string(), value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        for kind in [
+            ErrorKind::DbError,
+            ErrorKind::TonicError,
+            ErrorKind::MongoDbOperateError,
+            ErrorKind::RedisError,
+            ErrorKind::IOError,
+            ErrorKind::ServiceNotFound,
+        ] {
+            assert!(Error::with_details(kind, "transient").is_retryable());
+        }
+        for kind in [
+            ErrorKind::NotFound,
+            ErrorKind::InternalServer,
+            ErrorKind::BodyParsing,
+            ErrorKind::ParseError,
+            ErrorKind::BadRequest,
+            ErrorKind::BinCode,
+        ] {
+            assert!(!Error::with_details(kind, "permanent").is_retryable());
+        }
+
+        // the kinds are kept by the conversions
+        let err: Error = std::io::Error::other("connection reset").into();
+        assert!(err.is_retryable());
+        let err: Error = bincode::deserialize::<String>(&[1]).unwrap_err().into();
+        assert!(!err.is_retryable());
+    }
+}
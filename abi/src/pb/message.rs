@@ -53,6 +53,9 @@ pub struct Msg {
     /// / send sequence
     #[prost(int64, tag = "20")]
     pub send_seq: i64,
+    /// / device id of the sender, it is set by the gateway
+    #[prost(string, tag = "21")]
+    pub device_id: ::prost::alloc::string::String,
 }
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -72,6 +75,15 @@ pub struct Mention {
     #[prost(string, repeated, tag = "2")]
     pub user_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
+/// / the content of GroupRemoveMember for the protobuf and json clients,
+/// / it is the list of the member ids in bincode
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MemberIds {
+    #[prost(string, repeated, tag = "1")]
+    pub member_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -80,6 +92,61 @@ pub struct MsgRead {
     pub msg_seq: ::prost::alloc::vec::Vec<i64>,
     #[prost(string, tag = "2")]
     pub user_id: ::prost::alloc::string::String,
+    /// / the friend id of single chat or the group id,
+    /// / the read cursor of the conversation is moved to the max of msg_seq
+    #[prost(string, tag = "3")]
+    pub conversation_id: ::prost::alloc::string::String,
+}
+/// / the messages of the conversation are read until the seq,
+/// / the seq is the receiver sequence of the user
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadCursor {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub conversation_id: ::prost::alloc::string::String,
+    #[prost(int64, tag = "3")]
+    pub seq: i64,
+    /// / milliseconds timestamp
+    #[prost(int64, tag = "4")]
+    pub update_time: i64,
+    /// / the send time of the last message read, the seqs of the group members
+    /// / differ, the group messages are ordered by it
+    #[prost(int64, tag = "5")]
+    pub last_send_time: i64,
+}
+/// / the messages of the sender are read by the user
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReadReceipt {
+    /// / the reader
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag = "2")]
+    pub server_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// / the max send_seq of the messages read
+    #[prost(int64, tag = "3")]
+    pub send_seq: i64,
+    /// / milliseconds timestamp
+    #[prost(int64, tag = "4")]
+    pub read_time: i64,
+}
+/// / who have read the group message
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GroupReadStatus {
+    #[prost(string, tag = "1")]
+    pub server_id: ::prost::alloc::string::String,
+    #[prost(int32, tag = "2")]
+    pub read_count: i32,
+    #[prost(string, repeated, tag = "3")]
+    pub read_by: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "4")]
+    pub unread_by: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -323,6 +390,24 @@ pub struct User {
     #[prost(string, tag = "16")]
     pub signature: ::prost::alloc::string::String,
 }
+/// / the token of the device for the offline push notifications,
+/// / it is issued by the push service of the platform
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PushToken {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub device_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "PlatformType", tag = "3")]
+    pub platform: i32,
+    #[prost(string, tag = "4")]
+    pub token: ::prost::alloc::string::String,
+    /// / milliseconds timestamp
+    #[prost(int64, tag = "5")]
+    pub update_time: i64,
+}
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -611,6 +696,145 @@ pub struct SendGroupMsgRequest {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SendMsgResponse {}
+/// / the content of MsgTypeSync and MsgTypeSyncComplete message,
+/// / the last received seq and send_seq of the client, or the seqs synced to
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SyncSeq {
+    #[prost(int64, tag = "1")]
+    pub seq: i64,
+    #[prost(int64, tag = "2")]
+    pub send_seq: i64,
+    /// / milliseconds timestamp, the read cursors updated after it are synced
+    #[prost(int64, tag = "3")]
+    pub cursor_time: i64,
+}
+/// / the content of MsgTypePresence message
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Presence {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "PresenceStatus", tag = "2")]
+    pub status: i32,
+    /// / milliseconds timestamp of the last activity, 0 if the user hides it
+    #[prost(int64, tag = "3")]
+    pub last_seen: i64,
+}
+/// / the content of MsgTypeSignal message
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Signal {
+    #[prost(enumeration = "SignalType", tag = "1")]
+    pub signal_type: i32,
+}
+/// / forward the signal to the receivers held by the gateway
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SendSignalRequest {
+    #[prost(message, optional, tag = "1")]
+    pub message: ::core::option::Option<Msg>,
+    #[prost(string, repeated, tag = "2")]
+    pub receivers: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+/// / the content of MsgTypeKnockOff message,
+/// / device_id and platform describe the new session which displaced the connection
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KnockOff {
+    #[prost(enumeration = "KnockOffReason", tag = "1")]
+    pub reason: i32,
+    #[prost(string, tag = "2")]
+    pub device_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "PlatformType", tag = "3")]
+    pub platform: i32,
+    /// / the gateway to reconnect, only for Drain
+    #[prost(string, tag = "4")]
+    pub gateway: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KnockOffRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    /// / the device to be knocked off
+    #[prost(string, tag = "2")]
+    pub device_id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub knock_off: ::core::option::Option<KnockOff>,
+}
+/// / a connection held by the gateway
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Connection {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub device_id: ::prost::alloc::string::String,
+    #[prost(enumeration = "PlatformType", tag = "3")]
+    pub platform: i32,
+    #[prost(string, tag = "4")]
+    pub conn_id: ::prost::alloc::string::String,
+    /// / the rpc address of the gateway
+    #[prost(string, tag = "5")]
+    pub gateway: ::prost::alloc::string::String,
+    /// / milliseconds timestamp of the last frame received from the client
+    #[prost(int64, tag = "6")]
+    pub last_active: i64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GatewayStatsRequest {}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GatewayStats {
+    /// / the rpc address of the gateway
+    #[prost(string, tag = "1")]
+    pub gateway: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub users: u64,
+    #[prost(uint64, tag = "3")]
+    pub connections: u64,
+    /// / connection counts keyed by platform name
+    #[prost(map = "string, uint64", tag = "4")]
+    pub platforms: ::std::collections::HashMap<::prost::alloc::string::String, u64>,
+    #[prost(uint64, tag = "5")]
+    pub slow_consumer_evicted: u64,
+    #[prost(uint64, tag = "6")]
+    pub dropped_messages: u64,
+    #[prost(bool, tag = "7")]
+    pub draining: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UserConnectionsRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UserConnectionsResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub connections: ::prost::alloc::vec::Vec<Connection>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DisconnectRequest {
+    #[prost(string, tag = "1")]
+    pub user_id: ::prost::alloc::string::String,
+    /// / disconnect all devices of the user if it is empty
+    #[prost(string, tag = "2")]
+    pub device_id: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DrainRequest {}
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -715,7 +939,9 @@ pub struct SaveMaxSeqRequest {
     #[prost(string, tag = "1")]
     pub user_id: ::prost::alloc::string::String,
 }
-/// / user platform which login the system
+/// / user platform which login the system,
+/// / it is the category of the device, one user can login with multiple devices
+/// / of the same platform, the devices are distinguished by device id
 #[derive(
     serde::Serialize,
     serde::Deserialize,
@@ -733,6 +959,8 @@ pub struct SaveMaxSeqRequest {
 pub enum PlatformType {
     Desktop = 0,
     Mobile = 1,
+    Web = 2,
+    Tablet = 3,
 }
 impl PlatformType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -743,6 +971,8 @@ impl PlatformType {
         match self {
             PlatformType::Desktop => "Desktop",
             PlatformType::Mobile => "Mobile",
+            PlatformType::Web => "Web",
+            PlatformType::Tablet => "Tablet",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -750,6 +980,8 @@ impl PlatformType {
         match value {
             "Desktop" => Some(Self::Desktop),
             "Mobile" => Some(Self::Mobile),
+            "Web" => Some(Self::Web),
+            "Tablet" => Some(Self::Tablet),
             _ => None,
         }
     }
@@ -878,6 +1110,32 @@ pub enum MsgType {
     Notification = 25,
     Service = 26,
     FriendshipReceived = 27,
+    /// / the connection is closed by server because of login policy
+    KnockOff = 28,
+    /// / the client acknowledges the message with the seq
+    Ack = 29,
+    /// / the message with the server_id is delivered to the receiver
+    Delivered = 30,
+    /// / ephemeral signal like typing, it is never sequenced or stored
+    Signal = 31,
+    /// / presence of the user is changed, or the user sets its own status
+    Presence = 32,
+    /// / application level heartbeat for the clients which can not send websocket
+    /// / ping, the server replies the same type
+    Heartbeat = 33,
+    /// / the client asks for the messages after the seq and send_seq in SyncSeq
+    Sync = 34,
+    /// / all missing messages are sent, the live messages follow it
+    SyncComplete = 35,
+    /// / the read cursor of a conversation is moved by another device of the user,
+    /// / the content is ReadCursor
+    ReadCursor = 36,
+    /// / the messages are read by the receiver of single chat,
+    /// / it is sent to the sender, the content is ReadReceipt
+    ReadReceipt = 37,
+    /// / the group messages are read by a member, it is sent to the sender
+    /// / without seq, the group_id is set, the content is ReadReceipt
+    GroupReadReceipt = 38,
 }
 impl MsgType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -914,6 +1172,17 @@ impl MsgType {
             MsgType::Notification => "MsgTypeNotification",
             MsgType::Service => "MsgTypeService",
             MsgType::FriendshipReceived => "MsgTypeFriendshipReceived",
+            MsgType::KnockOff => "MsgTypeKnockOff",
+            MsgType::Ack => "MsgTypeAck",
+            MsgType::Delivered => "MsgTypeDelivered",
+            MsgType::Signal => "MsgTypeSignal",
+            MsgType::Presence => "MsgTypePresence",
+            MsgType::Heartbeat => "MsgTypeHeartbeat",
+            MsgType::Sync => "MsgTypeSync",
+            MsgType::SyncComplete => "MsgTypeSyncComplete",
+            MsgType::ReadCursor => "MsgTypeReadCursor",
+            MsgType::ReadReceipt => "MsgTypeReadReceipt",
+            MsgType::GroupReadReceipt => "MsgTypeGroupReadReceipt",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -947,6 +1216,17 @@ impl MsgType {
             "MsgTypeNotification" => Some(Self::Notification),
             "MsgTypeService" => Some(Self::Service),
             "MsgTypeFriendshipReceived" => Some(Self::FriendshipReceived),
+            "MsgTypeKnockOff" => Some(Self::KnockOff),
+            "MsgTypeAck" => Some(Self::Ack),
+            "MsgTypeDelivered" => Some(Self::Delivered),
+            "MsgTypeSignal" => Some(Self::Signal),
+            "MsgTypePresence" => Some(Self::Presence),
+            "MsgTypeHeartbeat" => Some(Self::Heartbeat),
+            "MsgTypeSync" => Some(Self::Sync),
+            "MsgTypeSyncComplete" => Some(Self::SyncComplete),
+            "MsgTypeReadCursor" => Some(Self::ReadCursor),
+            "MsgTypeReadReceipt" => Some(Self::ReadReceipt),
+            "MsgTypeGroupReadReceipt" => Some(Self::GroupReadReceipt),
             _ => None,
         }
     }
@@ -1019,6 +1299,150 @@ impl GroupMemberRole {
         }
     }
 }
+/// / presence status of the user
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    ::prost::Enumeration,
+)]
+#[repr(i32)]
+pub enum PresenceStatus {
+    Offline = 0,
+    Online = 1,
+    Away = 2,
+    Busy = 3,
+}
+impl PresenceStatus {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            PresenceStatus::Offline => "Offline",
+            PresenceStatus::Online => "Online",
+            PresenceStatus::Away => "Away",
+            PresenceStatus::Busy => "Busy",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Offline" => Some(Self::Offline),
+            "Online" => Some(Self::Online),
+            "Away" => Some(Self::Away),
+            "Busy" => Some(Self::Busy),
+            _ => None,
+        }
+    }
+}
+/// / the kind of ephemeral signal
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    ::prost::Enumeration,
+)]
+#[repr(i32)]
+pub enum SignalType {
+    Typing = 0,
+    Recording = 1,
+    Focus = 2,
+    /// / stop typing/recording, or leave the conversation
+    Idle = 3,
+}
+impl SignalType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SignalType::Typing => "Typing",
+            SignalType::Recording => "Recording",
+            SignalType::Focus => "Focus",
+            SignalType::Idle => "Idle",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Typing" => Some(Self::Typing),
+            "Recording" => Some(Self::Recording),
+            "Focus" => Some(Self::Focus),
+            "Idle" => Some(Self::Idle),
+            _ => None,
+        }
+    }
+}
+/// / why the connection is knocked off
+#[derive(
+    serde::Serialize,
+    serde::Deserialize,
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    ::prost::Enumeration,
+)]
+#[repr(i32)]
+pub enum KnockOffReason {
+    /// / the same device connected again
+    SameDevice = 0,
+    /// / another device of the same platform logged in
+    SamePlatform = 1,
+    /// / another device logged in
+    OtherDevice = 2,
+    /// / the gateway is shutting down, reconnect to the given gateway
+    Drain = 3,
+    /// / disconnected by the administrator
+    Admin = 4,
+}
+impl KnockOffReason {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            KnockOffReason::SameDevice => "SameDevice",
+            KnockOffReason::SamePlatform => "SamePlatform",
+            KnockOffReason::OtherDevice => "OtherDevice",
+            KnockOffReason::Drain => "Drain",
+            KnockOffReason::Admin => "Admin",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SameDevice" => Some(Self::SameDevice),
+            "SamePlatform" => Some(Self::SamePlatform),
+            "OtherDevice" => Some(Self::OtherDevice),
+            "Drain" => Some(Self::Drain),
+            "Admin" => Some(Self::Admin),
+            _ => None,
+        }
+    }
+}
 /// Generated client implementations.
 pub mod msg_service_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -1158,6 +1582,116 @@ pub mod msg_service_client {
                 .insert(GrpcMethod::new("message.MsgService", "SendGroupMsgToUser"));
             self.inner.unary(req, path, codec).await
         }
+        /// close the connection of the device which is displaced by a new session
+        pub async fn knock_off(
+            &mut self,
+            request: impl tonic::IntoRequest<super::KnockOffRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/message.MsgService/KnockOff");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "KnockOff"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// send ephemeral signal to users by websocket
+        pub async fn send_signal(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SendSignalRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/message.MsgService/SendSignal");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "SendSignal"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// the connection counts of the gateway
+        pub async fn get_stats(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GatewayStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::GatewayStats>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/message.MsgService/GetStats");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "GetStats"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// the connections of the user held by the gateway
+        pub async fn get_user_connections(
+            &mut self,
+            request: impl tonic::IntoRequest<super::UserConnectionsRequest>,
+        ) -> std::result::Result<tonic::Response<super::UserConnectionsResponse>, tonic::Status>
+        {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path =
+                http::uri::PathAndQuery::from_static("/message.MsgService/GetUserConnections");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "GetUserConnections"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// close the connections of the user or device by the administrator
+        pub async fn disconnect(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DisconnectRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/message.MsgService/Disconnect");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "Disconnect"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// put the gateway into drain mode
+        pub async fn drain(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DrainRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status> {
+            self.inner.ready().await.map_err(|e| {
+                tonic::Status::new(
+                    tonic::Code::Unknown,
+                    format!("Service was not ready: {}", e.into()),
+                )
+            })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/message.MsgService/Drain");
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("message.MsgService", "Drain"));
+            self.inner.unary(req, path, codec).await
+        }
     }
 }
 /// Generated client implementations.
@@ -1287,6 +1821,36 @@ pub mod msg_service_server {
             &self,
             request: tonic::Request<super::SendGroupMsgRequest>,
         ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status>;
+        /// close the connection of the device which is displaced by a new session
+        async fn knock_off(
+            &self,
+            request: tonic::Request<super::KnockOffRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status>;
+        /// send ephemeral signal to users by websocket
+        async fn send_signal(
+            &self,
+            request: tonic::Request<super::SendSignalRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status>;
+        /// the connection counts of the gateway
+        async fn get_stats(
+            &self,
+            request: tonic::Request<super::GatewayStatsRequest>,
+        ) -> std::result::Result<tonic::Response<super::GatewayStats>, tonic::Status>;
+        /// the connections of the user held by the gateway
+        async fn get_user_connections(
+            &self,
+            request: tonic::Request<super::UserConnectionsRequest>,
+        ) -> std::result::Result<tonic::Response<super::UserConnectionsResponse>, tonic::Status>;
+        /// close the connections of the user or device by the administrator
+        async fn disconnect(
+            &self,
+            request: tonic::Request<super::DisconnectRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status>;
+        /// put the gateway into drain mode
+        async fn drain(
+            &self,
+            request: tonic::Request<super::DrainRequest>,
+        ) -> std::result::Result<tonic::Response<super::SendMsgResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct MsgServiceServer<T: MsgService> {
@@ -1486,6 +2050,244 @@ pub mod msg_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/message.MsgService/KnockOff" => {
+                    #[allow(non_camel_case_types)]
+                    struct KnockOffSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::KnockOffRequest> for KnockOffSvc<T> {
+                        type Response = super::SendMsgResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::KnockOffRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as MsgService>::knock_off(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KnockOffSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/message.MsgService/SendSignal" => {
+                    #[allow(non_camel_case_types)]
+                    struct SendSignalSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::SendSignalRequest> for SendSignalSvc<T> {
+                        type Response = super::SendMsgResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SendSignalRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MsgService>::send_signal(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SendSignalSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/message.MsgService/GetStats" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetStatsSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::GatewayStatsRequest> for GetStatsSvc<T> {
+                        type Response = super::GatewayStats;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GatewayStatsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as MsgService>::get_stats(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetStatsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/message.MsgService/GetUserConnections" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetUserConnectionsSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::UserConnectionsRequest>
+                        for GetUserConnectionsSvc<T>
+                    {
+                        type Response = super::UserConnectionsResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::UserConnectionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MsgService>::get_user_connections(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetUserConnectionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/message.MsgService/Disconnect" => {
+                    #[allow(non_camel_case_types)]
+                    struct DisconnectSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::DisconnectRequest> for DisconnectSvc<T> {
+                        type Response = super::SendMsgResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DisconnectRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as MsgService>::disconnect(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DisconnectSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/message.MsgService/Drain" => {
+                    #[allow(non_camel_case_types)]
+                    struct DrainSvc<T: MsgService>(pub Arc<T>);
+                    impl<T: MsgService> tonic::server::UnaryService<super::DrainRequest> for DrainSvc<T> {
+                        type Response = super::SendMsgResponse;
+                        type Future = BoxFuture<tonic::Response<Self::Response>, tonic::Status>;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DrainRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut =
+                                async move { <T as MsgService>::drain(&inner, request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DrainSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => Box::pin(async move {
                     Ok(http::Response::builder()
                         .status(200)
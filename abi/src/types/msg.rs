@@ -45,6 +45,7 @@ impl TryFrom<Document> for Msg {
             related_msg_id: value
                 .get_str("related_msg_id")
                 .map_or(None, |v| Some(v.to_string())),
+            device_id: value.get_str("device_id").unwrap_or_default().to_string(),
         })
     }
 }
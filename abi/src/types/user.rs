@@ -1,4 +1,4 @@
-use crate::message::{User, UserWithMatchType};
+use crate::message::{PushToken, User, UserWithMatchType};
 use sqlx::postgres::PgRow;
 use sqlx::{Error, FromRow, Row};
 
@@ -42,3 +42,15 @@ impl FromRow<'_, PgRow> for UserWithMatchType {
         })
     }
 }
+
+impl FromRow<'_, PgRow> for PushToken {
+    fn from_row(row: &'_ PgRow) -> Result<Self, Error> {
+        Ok(PushToken {
+            user_id: row.try_get("user_id")?,
+            device_id: row.try_get("device_id")?,
+            platform: row.try_get("platform")?,
+            token: row.try_get("token")?,
+            update_time: row.try_get("update_time")?,
+        })
+    }
+}
@@ -6,7 +6,6 @@ use axum::{
     extract::{FromRequest, MatchedPath},
     http::StatusCode,
 };
-use jsonwebtoken::{DecodingKey, Validation, decode};
 use serde::de::DeserializeOwned;
 
 use abi::errors::Error;
@@ -16,9 +15,6 @@ use crate::handlers::users::Claims;
 
 pub struct JsonWithAuthExtractor<T>(pub T);
 
-const AUTHORIZATION_HEADER: &str = "Authorization";
-const BEARER: &str = "Bearer";
-
 // The AUTHENTICATION here uses the browser fingerprint plus the user ID,
 // otherwise it is not reasonable to force a user to log off if the computer
 // is permanently on before the expiration date
@@ -45,23 +41,9 @@ where
             .unwrap_or(String::new());
         let app_state = AppState::from_ref(state);
 
-        if let Some(header) = parts.headers.get(AUTHORIZATION_HEADER) {
-            // analyze the header
-            let header = header.to_str().unwrap_or("");
-            if !header.starts_with(BEARER) {
-                return Err((
-                    StatusCode::UNAUTHORIZED,
-                    Error::unauthorized_with_details(path),
-                ));
-            }
-            let header: Vec<&str> = header.split_whitespace().collect();
-
-            if let Err(err) = decode::<Claims>(
-                header[1],
-                &DecodingKey::from_secret(app_state.jwt_secret.as_bytes()),
-                &Validation::default(),
-            ) {
-                return Err((StatusCode::INTERNAL_SERVER_ERROR, Error::internal(err)));
+        if let Some(token) = bearer_token(&parts) {
+            if let Err(err) = Claims::verify(token, &app_state.jwt_secret) {
+                return Err((StatusCode::UNAUTHORIZED, err));
             }
 
             let req = Request::from_parts(parts, body);
@@ -104,24 +86,9 @@ where
             .unwrap_or(String::new());
         let app_state = AppState::from_ref(state);
 
-        if let Some(header) = parts.headers.get(AUTHORIZATION_HEADER) {
-            // 解析请求头
-            let header = header.to_str().unwrap_or("");
-            if !header.starts_with(BEARER) {
-                return Err((
-                    StatusCode::UNAUTHORIZED,
-                    Error::unauthorized_with_details(path),
-                ));
-            }
-
-            let header: Vec<&str> = header.split_whitespace().collect();
-
-            if let Err(err) = decode::<Claims>(
-                header[1],
-                &DecodingKey::from_secret(app_state.jwt_secret.as_bytes()),
-                &Validation::default(),
-            ) {
-                return Err((StatusCode::UNAUTHORIZED, Error::unauthorized(err, path)));
+        if let Some(token) = bearer_token(parts) {
+            if let Err(err) = Claims::verify(token, &app_state.jwt_secret) {
+                return Err((StatusCode::UNAUTHORIZED, err));
             }
 
             match axum::extract::Path::<T>::from_request_parts(parts, state).await {
@@ -154,3 +121,78 @@ where
         }
     }
 }
+
+/// the token of the `Authorization: Bearer <token>` header
+fn bearer_token(parts: &Parts) -> Option<&str> {
+    parts
+        .headers
+        .get(utils::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(utils::bearer_token)
+}
+
+/// the verified claims of the bearer token, the subject is the id of the user
+pub struct ClaimsExtractor(pub Claims);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClaimsExtractor
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Error);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let path = parts
+            .extract::<MatchedPath>()
+            .await
+            .map(|path| path.as_str().to_owned())
+            .ok()
+            .unwrap_or(String::new());
+        let app_state = AppState::from_ref(state);
+
+        let Some(token) = bearer_token(parts) else {
+            return Err((
+                StatusCode::UNAUTHORIZED,
+                Error::unauthorized_with_details(path),
+            ));
+        };
+
+        match Claims::verify(token, &app_state.jwt_secret) {
+            Ok(claims) => Ok(Self(claims)),
+            Err(err) => Err((StatusCode::UNAUTHORIZED, err)),
+        }
+    }
+}
+
+/// the request of admin api, which carries the admin token as bearer token
+pub struct AdminExtractor;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminExtractor
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, Error);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let path = parts
+            .extract::<MatchedPath>()
+            .await
+            .map(|path| path.as_str().to_owned())
+            .ok()
+            .unwrap_or(String::new());
+        let app_state = AppState::from_ref(state);
+
+        let admin_token = app_state.ws_config.admin_token.as_deref();
+        if utils::check_admin_token(admin_token, bearer_token(parts)) {
+            Ok(Self)
+        } else {
+            Err((
+                StatusCode::UNAUTHORIZED,
+                Error::unauthorized_with_details(path),
+            ))
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use axum::Json;
+use axum::extract::State;
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tonic::transport::Channel;
+use tracing::error;
+use utils::admin_request;
+
+use abi::errors::Error;
+use abi::message::msg_service_client::MsgServiceClient;
+use abi::message::{
+    Connection, DisconnectRequest, DrainRequest, GatewayStats, GatewayStatsRequest,
+    UserConnectionsRequest,
+};
+
+use crate::AppState;
+use crate::api_utils::custom_extract::{AdminExtractor, PathExtractor};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct DrainGateway {
+    /// the rpc address of the gateway
+    pub gateway: String,
+}
+
+/// connect to all gateways registered in the service center,
+/// return the rpc address and client of each gateway
+async fn gateways(app_state: &AppState) -> Result<Vec<(String, MsgServiceClient<Channel>)>, Error> {
+    let mut service_center = app_state.service_center.clone();
+    let services = service_center
+        .query_with_name(app_state.gateway_rpc_name.clone())
+        .await
+        .map_err(|e| Error::internal_with_details(e.to_string()))?;
+
+    let mut gateways = Vec::with_capacity(services.len());
+    for service in services {
+        let gateway = format!("{}:{}", service.address, service.port);
+        let endpoint = format!("{}://{}", service.scheme, gateway);
+        match MsgServiceClient::connect(endpoint).await {
+            Ok(client) => gateways.push((gateway, client)),
+            Err(e) => error!("connect to gateway {} error: {:?}", gateway, e),
+        }
+    }
+    Ok(gateways)
+}
+
+/// the connection counts of each gateway
+pub async fn get_gateway_stats(
+    State(app_state): State<AppState>,
+    _: AdminExtractor,
+) -> Result<Json<Vec<GatewayStats>>, Error> {
+    let gateways = gateways(&app_state).await?;
+    let admin_token = app_state.ws_config.admin_token.as_deref();
+    let results = join_all(
+        gateways
+            .into_iter()
+            .map(|(gateway, mut client)| async move {
+                client
+                    .get_stats(admin_request(GatewayStatsRequest {}, admin_token))
+                    .await
+                    .map(|res| res.into_inner())
+                    .map_err(|e| error!("query stats of gateway {} error: {:?}", gateway, e))
+            }),
+    )
+    .await;
+    Ok(Json(results.into_iter().flatten().collect()))
+}
+
+/// the connections of the user on all gateways
+pub async fn get_user_connections(
+    State(app_state): State<AppState>,
+    _: AdminExtractor,
+    PathExtractor(user_id): PathExtractor<String>,
+) -> Result<Json<Vec<Connection>>, Error> {
+    let gateways = gateways(&app_state).await?;
+    let admin_token = app_state.ws_config.admin_token.as_deref();
+    let results = join_all(gateways.into_iter().map(|(gateway, mut client)| {
+        let request = admin_request(
+            UserConnectionsRequest {
+                user_id: user_id.clone(),
+            },
+            admin_token,
+        );
+        async move {
+            client
+                .get_user_connections(request)
+                .await
+                .map(|res| res.into_inner().connections)
+                .map_err(|e| error!("query connections of gateway {} error: {:?}", gateway, e))
+        }
+    }))
+    .await;
+    Ok(Json(results.into_iter().flatten().flatten().collect()))
+}
+
+/// close all connections of the user
+pub async fn disconnect_user(
+    State(app_state): State<AppState>,
+    _: AdminExtractor,
+    PathExtractor(user_id): PathExtractor<String>,
+) -> Result<(), Error> {
+    disconnect(&app_state, user_id, String::new()).await
+}
+
+/// close the connection of the device
+pub async fn disconnect_device(
+    State(app_state): State<AppState>,
+    _: AdminExtractor,
+    PathExtractor((user_id, device_id)): PathExtractor<(String, String)>,
+) -> Result<(), Error> {
+    disconnect(&app_state, user_id, device_id).await
+}
+
+/// the gateways which do not hold the connection just ignore the request
+async fn disconnect(app_state: &AppState, user_id: String, device_id: String) -> Result<(), Error> {
+    let gateways = gateways(app_state).await?;
+    let admin_token = app_state.ws_config.admin_token.as_deref();
+    join_all(gateways.into_iter().map(|(gateway, mut client)| {
+        let request = DisconnectRequest {
+            user_id: user_id.clone(),
+            device_id: device_id.clone(),
+        };
+        let request = admin_request(request, admin_token);
+        async move {
+            if let Err(e) = client.disconnect(request).await {
+                error!("disconnect on gateway {} error: {:?}", gateway, e);
+            }
+        }
+    }))
+    .await;
+    Ok(())
+}
+
+/// put the gateway into drain mode
+pub async fn drain_gateway(
+    State(app_state): State<AppState>,
+    _: AdminExtractor,
+    Json(req): Json<DrainGateway>,
+) -> Result<(), Error> {
+    let (_, mut client) = gateways(&app_state)
+        .await?
+        .into_iter()
+        .find(|(gateway, _)| *gateway == req.gateway)
+        .ok_or_else(|| Error::not_found_with_details(req.gateway))?;
+    let admin_token = app_state.ws_config.admin_token.as_deref();
+    client
+        .drain(admin_request(DrainRequest {}, admin_token))
+        .await?;
+    Ok(())
+}
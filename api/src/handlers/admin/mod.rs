@@ -0,0 +1 @@
+pub mod admin_handlers;
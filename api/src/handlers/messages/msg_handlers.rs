@@ -1,12 +1,16 @@
+use std::collections::HashSet;
+
 use axum::Json;
 use axum::extract::State;
 use serde::{Deserialize, Serialize};
 
 use abi::errors::Error;
-use abi::message::{DelMsgRequest, GetDbMessagesRequest, Msg};
+use abi::message::{DelMsgRequest, GetDbMessagesRequest, GroupReadStatus, Msg, MsgType};
 
 use crate::AppState;
-use crate::api_utils::custom_extract::{JsonWithAuthExtractor, PathWithAuthExtractor};
+use crate::api_utils::custom_extract::{
+    ClaimsExtractor, JsonWithAuthExtractor, PathExtractor, PathWithAuthExtractor,
+};
 
 // message handler, offer the ability to pull offline message
 // #[allow(dead_code)]
@@ -86,3 +90,56 @@ pub async fn del_msg(
         .await?;
     Ok(())
 }
+
+/// who have read the group message, only the sender can query it.
+/// the members whose read cursor of the group reaches the send time of the message have read it
+pub async fn get_group_read_status(
+    State(state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    PathExtractor((user_id, server_id)): PathExtractor<(String, String)>,
+) -> Result<Json<GroupReadStatus>, Error> {
+    claims.check_subject(&user_id)?;
+    let msg = state
+        .msg_box
+        .get_message(&server_id)
+        .await?
+        .ok_or_else(|| Error::not_found_with_details(server_id.clone()))?;
+    if msg.send_id != user_id || msg.msg_type != MsgType::GroupMsg as i32 {
+        return Err(Error::unauthorized_with_details(
+            "only the sender can query the read status",
+        ));
+    }
+    // the copy of the sender is received by the group
+    let group_id = if msg.group_id.is_empty() {
+        msg.receiver_id
+    } else {
+        msg.group_id
+    };
+
+    let readers: HashSet<String> = state
+        .msg_box
+        .get_group_readers(&group_id, msg.send_time)
+        .await?
+        .into_iter()
+        .collect();
+    let mut members = state.db.group.query_group_members_id(&group_id).await?;
+    members.retain(|id| *id != user_id);
+    // the members who disabled the read receipts are not reported at all
+    let hidden: HashSet<String> = state
+        .cache
+        .query_read_receipt_disabled(&members)
+        .await?
+        .into_iter()
+        .collect();
+    let (read_by, unread_by): (Vec<String>, Vec<String>) = members
+        .into_iter()
+        .filter(|id| !hidden.contains(id))
+        .partition(|id| readers.contains(id));
+
+    Ok(Json(GroupReadStatus {
+        server_id,
+        read_count: read_by.len() as i32,
+        read_by,
+        unread_by,
+    }))
+}
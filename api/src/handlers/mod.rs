@@ -1,3 +1,4 @@
+pub(crate) mod admin;
 pub(crate) mod files;
 pub(crate) mod friends;
 pub(crate) mod groups;
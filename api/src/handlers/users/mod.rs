@@ -2,16 +2,22 @@ use std::net::{IpAddr, SocketAddr};
 
 use axum::Json;
 use base64::prelude::*;
-use jsonwebtoken::{EncodingKey, Header, encode};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
 use serde::{Deserialize, Serialize};
 
 use abi::errors::Error;
 use abi::message::User;
 
 mod oauth2;
+mod presence_handlers;
+mod privacy_handlers;
+mod push_handlers;
 mod user_handlers;
 
 pub use oauth2::*;
+pub use presence_handlers::*;
+pub use privacy_handlers::*;
+pub use push_handlers::*;
 use tracing::error;
 pub use user_handlers::*;
 use xdb::search_by_ip;
@@ -64,15 +70,54 @@ pub struct Claims {
     pub sub: String,
     pub exp: i64,
     pub iat: i64,
+    /// the tokens issued before the version is introduced have no such claim, they are version 0
+    #[serde(default)]
+    pub ver: u32,
 }
 
 const EXPIRES: i64 = 60 * 60 * 4;
 
+/// the version of the claims, it is bumped when the meaning of a claim is changed,
+/// the tokens of the older versions are rejected and the user has to login again.
+/// version 1: the subject is the user id instead of the user name
+pub const CLAIMS_VERSION: u32 = 1;
+
 impl Claims {
     pub fn new(sub: String) -> Self {
         let now = chrono::Utc::now().timestamp();
         let exp = now + EXPIRES;
-        Self { sub, exp, iat: now }
+        Self {
+            sub,
+            exp,
+            iat: now,
+            ver: CLAIMS_VERSION,
+        }
+    }
+
+    /// verify the token and its version, return the claims
+    pub fn verify(token: &str, jwt_secret: &str) -> Result<Self, Error> {
+        let data = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(jwt_secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|err| Error::unauthorized(err, "token is invalid"))?;
+        if data.claims.ver < CLAIMS_VERSION {
+            return Err(Error::unauthorized_with_details(
+                "token is issued by an older version, please login again",
+            ));
+        }
+        Ok(data.claims)
+    }
+
+    /// the user can only operate on the data of its own
+    pub fn check_subject(&self, user_id: &str) -> Result<(), Error> {
+        if self.sub != user_id {
+            return Err(Error::unauthorized_with_details(
+                "the user id does not match the token",
+            ));
+        }
+        Ok(())
     }
 }
 
@@ -81,8 +126,8 @@ pub async fn gen_token(
     mut user: User,
     addr: SocketAddr,
 ) -> Result<Json<Token>, Error> {
-    // generate token
-    let mut claims = Claims::new(user.name.clone());
+    // generate token, the subject is the user id which is verified by the websocket gateway
+    let mut claims = Claims::new(user.id.clone());
 
     let token = encode(
         &Header::default(),
@@ -165,3 +210,23 @@ impl ModifyPwdRequest {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_claims_version() {
+        let secret = "secret";
+        let key = EncodingKey::from_secret(secret.as_bytes());
+
+        let token = encode(&Header::default(), &Claims::new("id".to_string()), &key).unwrap();
+        assert_eq!(Claims::verify(&token, secret).unwrap().sub, "id");
+
+        // the token issued before the version is introduced, the subject is the user name
+        let now = chrono::Utc::now().timestamp();
+        let old = serde_json::json!({"sub": "name", "exp": now + EXPIRES, "iat": now});
+        let token = encode(&Header::default(), &old, &key).unwrap();
+        assert!(Claims::verify(&token, secret).is_err());
+    }
+}
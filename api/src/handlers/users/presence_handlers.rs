@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+use axum::Json;
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+
+use abi::errors::Error;
+use abi::message::{FriendshipStatus, Presence};
+
+use crate::AppState;
+use crate::api_utils::custom_extract::{ClaimsExtractor, JsonExtractor};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PresenceQuery {
+    pub user_id: String,
+    pub friend_ids: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PresencePrivacy {
+    pub user_id: String,
+    pub hide_last_seen: bool,
+}
+
+/// query the presence of the friends in batch,
+/// the users who are not friends of the requester are ignored
+pub async fn query_presence(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(query): JsonExtractor<PresenceQuery>,
+) -> Result<Json<Vec<Presence>>, Error> {
+    claims.check_subject(&query.user_id)?;
+    let friends: HashSet<String> = app_state
+        .db
+        .friend
+        .get_friend_list(&query.user_id, 0)
+        .await?
+        .into_iter()
+        .filter(|friend| friend.status == FriendshipStatus::Accepted as i32)
+        .map(|friend| friend.friend_id)
+        .collect();
+    let ids: Vec<String> = query
+        .friend_ids
+        .into_iter()
+        .filter(|id| friends.contains(id))
+        .collect();
+    if ids.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+
+    let list = app_state.cache.query_presence(&ids).await?;
+    Ok(Json(list))
+}
+
+/// hide the last seen time from the friends
+pub async fn update_presence_privacy(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(privacy): JsonExtractor<PresencePrivacy>,
+) -> Result<(), Error> {
+    claims.check_subject(&privacy.user_id)?;
+    app_state
+        .cache
+        .set_hide_last_seen(&privacy.user_id, privacy.hide_last_seen)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+
+use abi::errors::Error;
+
+use crate::AppState;
+use crate::api_utils::custom_extract::{ClaimsExtractor, JsonExtractor};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ReadReceiptPrivacy {
+    pub user_id: String,
+    pub disabled: bool,
+}
+
+/// stop telling the senders that their messages are read
+pub async fn update_read_receipt_privacy(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(privacy): JsonExtractor<ReadReceiptPrivacy>,
+) -> Result<(), Error> {
+    claims.check_subject(&privacy.user_id)?;
+    app_state
+        .cache
+        .set_read_receipt_disabled(&privacy.user_id, privacy.disabled)
+        .await?;
+    Ok(())
+}
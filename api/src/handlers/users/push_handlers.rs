@@ -0,0 +1,67 @@
+use axum::extract::State;
+use serde::{Deserialize, Serialize};
+
+use abi::errors::Error;
+use abi::message::PushToken;
+
+use crate::AppState;
+use crate::api_utils::custom_extract::{ClaimsExtractor, JsonExtractor};
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PushTokenDelete {
+    pub user_id: String,
+    pub device_id: String,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ConversationMute {
+    pub user_id: String,
+    /// the friend id of single chat or the group id
+    pub conversation_id: String,
+    pub muted: bool,
+}
+
+/// the device receives the push notifications when the user is offline,
+/// the token of the device is replaced if it is registered already
+pub async fn register_push_token(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(mut token): JsonExtractor<PushToken>,
+) -> Result<(), Error> {
+    claims.check_subject(&token.user_id)?;
+    if token.device_id.is_empty() || token.token.is_empty() {
+        return Err(Error::bad_request("device id or token is empty"));
+    }
+    token.update_time = chrono::Utc::now().timestamp_millis();
+    app_state.db.push.save_push_token(&token).await?;
+    Ok(())
+}
+
+/// stop the push notifications of the device, like logging out
+pub async fn delete_push_token(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(token): JsonExtractor<PushTokenDelete>,
+) -> Result<(), Error> {
+    claims.check_subject(&token.user_id)?;
+    app_state
+        .db
+        .push
+        .delete_push_token(&token.user_id, &token.device_id)
+        .await?;
+    Ok(())
+}
+
+/// the muted conversation is not notified unless the user is mentioned
+pub async fn update_conversation_mute(
+    State(app_state): State<AppState>,
+    ClaimsExtractor(claims): ClaimsExtractor,
+    JsonExtractor(mute): JsonExtractor<ConversationMute>,
+) -> Result<(), Error> {
+    claims.check_subject(&mute.user_id)?;
+    app_state
+        .cache
+        .set_conversation_muted(&mute.user_id, &mute.conversation_id, mute.muted)
+        .await?;
+    Ok(())
+}
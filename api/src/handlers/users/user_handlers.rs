@@ -2,7 +2,7 @@ use std::net::SocketAddr;
 
 use axum::Json;
 use axum::extract::{ConnectInfo, State};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::{EncodingKey, Header, encode};
 use lettre::message::header::ContentType;
 use lettre::message::{MultiPart, SinglePart};
 use lettre::transport::smtp::authentication::Credentials;
@@ -27,18 +27,15 @@ pub async fn refresh_token(
     State(app_state): State<AppState>,
     PathExtractor((token, is_refresh)): PathExtractor<(String, bool)>,
 ) -> Result<String, Error> {
-    let claim = match decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(app_state.jwt_secret.as_bytes()),
-        &Validation::default(),
-    ) {
-        Ok(data) => data,
+    // the old tokens carry the user name as subject, they can not be refreshed
+    let claim = match Claims::verify(&token, &app_state.jwt_secret) {
+        Ok(claims) => claims,
         Err(err) => {
-            debug!("token is expired");
-            return Err(Error::unauthorized(err, "/refresh_token"));
+            debug!("token is expired or outdated");
+            return Err(err);
         }
     };
-    let mut claims = Claims::new(claim.claims.sub.clone());
+    let mut claims = Claims::new(claim.sub.clone());
     if is_refresh {
         claims.exp += REFRESH_EXPIRES;
     }
@@ -29,6 +29,10 @@ pub struct AppState {
     pub cache: Arc<dyn Cache>,
     pub oss: Arc<dyn Oss>,
     pub ws_lb: Arc<lb::LoadBalancer>,
+    /// query the gateways for the admin api
+    pub service_center: ServiceClient,
+    /// the rpc service name of the gateways
+    pub gateway_rpc_name: String,
     pub ws_config: WsServerConfig,
     pub mail_config: MailConfig,
     pub jwt_secret: String,
@@ -45,7 +49,9 @@ pub struct OAuth2Clients {
 impl AppState {
     pub async fn new(config: &Config) -> Self {
         let db = Arc::new(DbRepo::new(config).await);
-        let msg_box = msg_rec_box_repo(config).await;
+        let msg_box = msg_rec_box_repo(config)
+            .await
+            .expect("message box can't open");
 
         let chat_rpc = utils::get_rpc_client(config, config.rpc.chat.name.clone())
             .await
@@ -68,7 +74,7 @@ impl AppState {
             lb::LoadBalancer::new(
                 config.websocket.name.clone(),
                 config.server.ws_lb_strategy.clone(),
-                client,
+                client.clone(),
             )
             .await,
         );
@@ -82,6 +88,8 @@ impl AppState {
             cache,
             oss,
             ws_lb,
+            service_center: client,
+            gateway_rpc_name: config.rpc.ws.name.clone(),
             ws_config,
             mail_config,
             jwt_secret: config.server.jwt_secret.clone(),
@@ -3,6 +3,9 @@ use axum::extract::DefaultBodyLimit;
 use axum::routing::{delete, get, post, put};
 
 use crate::AppState;
+use crate::handlers::admin::admin_handlers::{
+    disconnect_device, disconnect_user, drain_gateway, get_gateway_stats, get_user_connections,
+};
 use crate::handlers::files::file::{get_avatar_by_name, get_file_by_name, upload, upload_avatar};
 use crate::handlers::friends::friend_handlers::{
     agree, create_friendship, delete_friend, get_apply_list_by_user_id,
@@ -12,10 +15,14 @@ use crate::handlers::groups::group_handlers::{
     create_group_handler, delete_group_handler, get_group, get_group_and_members,
     get_group_members, invite_new_members, remove_member, update_group_handler,
 };
-use crate::handlers::messages::msg_handlers::{del_msg, get_seq, pull_offline_messages};
+use crate::handlers::messages::msg_handlers::{
+    del_msg, get_group_read_status, get_seq, pull_offline_messages,
+};
 use crate::handlers::users::{
-    create_user, get_user_by_id, github_callback, github_login, google_callback, google_login,
-    login, logout, modify_pwd, refresh_token, search_user, send_email, update_user,
+    create_user, delete_push_token, get_user_by_id, github_callback, github_login, google_callback,
+    google_login, login, logout, modify_pwd, query_presence, refresh_token, register_push_token,
+    search_user, send_email, update_conversation_mute, update_presence_privacy,
+    update_read_receipt_privacy, update_user,
 };
 
 pub(crate) fn app_routes(state: AppState) -> Router {
@@ -25,6 +32,19 @@ pub(crate) fn app_routes(state: AppState) -> Router {
         .nest("/file", file_routes(state.clone()))
         .nest("/group", group_routes(state.clone()))
         .nest("/message", msg_routes(state.clone()))
+        .nest("/admin", admin_routes(state.clone()))
+}
+
+fn admin_routes(state: AppState) -> Router {
+    Router::new()
+        .route("/gateways", get(get_gateway_stats))
+        .route("/gateways/drain", post(drain_gateway))
+        .route(
+            "/users/:user_id",
+            get(get_user_connections).delete(disconnect_user),
+        )
+        .route("/users/:user_id/:device_id", delete(disconnect_device))
+        .with_state(state)
 }
 
 fn friend_routes(state: AppState) -> Router {
@@ -50,6 +70,14 @@ fn user_routes(state: AppState) -> Router {
         .route("/login", post(login))
         .route("/logout/:uuid", delete(logout))
         .route("/mail/send", post(send_email))
+        .route("/presence", post(query_presence))
+        .route("/presence/privacy", put(update_presence_privacy))
+        .route("/read_receipt/privacy", put(update_read_receipt_privacy))
+        .route(
+            "/push/token",
+            post(register_push_token).delete(delete_push_token),
+        )
+        .route("/mute", put(update_conversation_mute))
         .route("/auth/wechat", get(google_login))
         .route("/auth/wechat/callback", get(google_callback))
         .route("/auth/github", get(github_login))
@@ -90,6 +118,10 @@ fn msg_routes(state: AppState) -> Router {
     Router::new()
         .route("/", post(pull_offline_messages))
         .route("/seq/:user_id", get(get_seq))
+        .route(
+            "/group/read/:user_id/:server_id",
+            get(get_group_read_status),
+        )
         .route("/", delete(del_msg))
         .with_state(state)
 }
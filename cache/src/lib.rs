@@ -1,7 +1,8 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::sync::Arc;
 
-use abi::message::GroupMemSeq;
+use abi::message::{GroupMemSeq, Presence, PresenceStatus};
 use async_trait::async_trait;
 
 use abi::config::Config;
@@ -36,6 +37,11 @@ pub trait Cache: Sync + Send + Debug {
     /// increase receive sequence by user id
     async fn increase_seq(&self, user_id: &str) -> Result<(i64, i64, bool), Error>;
 
+    /// increase the seqs in one round trip, the users can be repeated,
+    /// the results are in the same order as the users
+    async fn increase_seq_batch(&self, user_ids: &[String])
+    -> Result<Vec<(i64, i64, bool)>, Error>;
+
     /// increase send sequence by user id
     async fn incr_send_seq(&self, user_id: &str) -> Result<(i64, i64, bool), Error>;
 
@@ -84,6 +90,110 @@ pub trait Cache: Sync + Send + Debug {
 
     /// online count
     async fn online_count(&self) -> Result<i64, Error>;
+
+    /// record the gateway which holds the user's connection of the device,
+    /// the gateway is the address of the gateway's rpc service
+    async fn register_gateway(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        platform: i32,
+        gateway: &str,
+    ) -> Result<(), Error>;
+
+    /// remove the user's connection record of the device,
+    /// only if the record still belongs to the given gateway
+    async fn unregister_gateway(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        gateway: &str,
+    ) -> Result<(), Error>;
+
+    /// query the gateways which hold the users' connections,
+    /// it returns user id -> gateways, the users without any connection are not included,
+    /// the connections held by the gateways whose lease is expired are ignored and removed
+    async fn query_user_gateways(
+        &self,
+        user_ids: &[String],
+    ) -> Result<HashMap<String, Vec<String>>, Error>;
+
+    /// query all connections of the user,
+    /// it returns the list of (device id, platform, gateway), the same as above for the dead gateways
+    async fn query_user_devices(&self, user_id: &str) -> Result<Vec<(String, i32, String)>, Error>;
+
+    /// keep the gateway alive for `ttl` seconds,
+    /// the connections registered by the gateway are ignored once the lease is expired
+    async fn refresh_gateway_lease(&self, gateway: &str, ttl: u64) -> Result<(), Error>;
+
+    /// remove all connection records of the gateway,
+    /// it is called when the gateway starts, the records are left by the last crashed run
+    async fn clear_gateway(&self, gateway: &str) -> Result<(), Error>;
+
+    /// set the presence status chosen by the user
+    async fn set_presence(&self, user_id: &str, status: PresenceStatus) -> Result<(), Error>;
+
+    /// record the last activity time of the user
+    async fn touch_presence(&self, user_id: &str, last_seen: i64) -> Result<(), Error>;
+
+    /// hide or show the last seen time to others
+    async fn set_hide_last_seen(&self, user_id: &str, hide: bool) -> Result<(), Error>;
+
+    /// query the presence of the users,
+    /// the user without any connection is offline, and the last seen is 0 if the user hides it
+    async fn query_presence(&self, user_ids: &[String]) -> Result<Vec<Presence>, Error>;
+
+    /// count the inbound messages of the user in the current window of `window` seconds,
+    /// the counter is shared by all gateways, add `count` messages and return the total
+    async fn incr_rate_counter(
+        &self,
+        user_id: &str,
+        category: &str,
+        window: u64,
+        count: u64,
+    ) -> Result<u64, Error>;
+
+    /// save the single-use ticket which stands for the user for `ttl` seconds,
+    /// it is used by the clients which can not carry the token in the headers
+    async fn save_connect_ticket(&self, ticket: &str, user_id: &str, ttl: u64)
+    -> Result<(), Error>;
+
+    /// take the ticket and return the user of it, the ticket can not be used again
+    async fn take_connect_ticket(&self, ticket: &str) -> Result<Option<String>, Error>;
+
+    /// stop or resume telling the senders that their messages are read by the user
+    async fn set_read_receipt_disabled(&self, user_id: &str, disabled: bool) -> Result<(), Error>;
+
+    /// the read receipts are sent by default
+    async fn is_read_receipt_disabled(&self, user_id: &str) -> Result<bool, Error>;
+
+    /// the users who disabled the read receipts
+    async fn query_read_receipt_disabled(&self, user_ids: &[String]) -> Result<Vec<String>, Error>;
+
+    /// stop or resume the offline push notifications of the conversation
+    async fn set_conversation_muted(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        muted: bool,
+    ) -> Result<(), Error>;
+
+    /// the users who muted the conversation
+    async fn query_muted_users(
+        &self,
+        conversation_id: &str,
+        user_ids: &[String],
+    ) -> Result<Vec<String>, Error>;
+
+    /// remember the seqs assigned to the receivers of the messages by the server id,
+    /// the redelivered message keeps them instead of increasing again
+    async fn save_msg_seq(&self, seqs: &[(String, Vec<GroupMemSeq>)]) -> Result<(), Error>;
+
+    /// the seqs assigned to the receivers of each message, empty if it is not assigned
+    async fn query_msg_seq(&self, server_ids: &[String]) -> Result<Vec<Vec<GroupMemSeq>>, Error>;
+
+    /// mark the message is pushed, return false if it is marked already
+    async fn mark_msg_pushed(&self, server_id: &str) -> Result<bool, Error>;
 }
 
 pub fn cache(config: &Config) -> Arc<dyn Cache> {
@@ -1,9 +1,13 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::Cache;
 use abi::config::Config;
 use abi::errors::Error;
-use abi::message::GroupMemSeq;
+use abi::message::{GroupMemSeq, Presence, PresenceStatus};
 use async_trait::async_trait;
 use redis::AsyncCommands;
+use redis::aio::MultiplexedConnection;
 
 /// group members id prefix
 const GROUP_MEMBERS_ID_PREFIX: &str = "group_members_id";
@@ -28,12 +32,50 @@ const IS_LOADED: &str = "seq_need_load";
 
 const SEQ_NO_NEED_LOAD: &str = "false";
 
+/// user connections prefix, user_gateway:user_id -> {device_id: gateway}
+const USER_GATEWAY_PREFIX: &str = "user_gateway";
+
+/// gateway lease prefix, gateway_lease:gateway -> 1, expires if the gateway stops refreshing it
+const GATEWAY_LEASE_PREFIX: &str = "gateway_lease";
+
+/// users registered on the gateway, gateway_users:gateway -> {user_id}
+const GATEWAY_USERS_PREFIX: &str = "gateway_users";
+
+/// user presence prefix, presence:user_id -> {status, last_seen, hide_last_seen}
+const PRESENCE_PREFIX: &str = "presence";
+
+/// user devices' platform prefix, user_platform:user_id -> {device_id: platform}
+const USER_PLATFORM_PREFIX: &str = "user_platform";
+
+/// user privacy settings prefix, privacy:user_id -> {read_receipt_disabled}
+const PRIVACY_PREFIX: &str = "privacy";
+
+/// muted conversations prefix, mute:user_id -> {conversation_id}
+const MUTE_PREFIX: &str = "mute";
+
+/// inbound rate counter prefix, rate_limit:user_id:category:window_index -> count
+const RATE_LIMIT_PREFIX: &str = "rate_limit";
+
+/// connect ticket prefix, connect_ticket:ticket -> user_id
+const CONNECT_TICKET_PREFIX: &str = "connect_ticket";
+
+/// assigned seqs prefix, msg_seq:server_id -> {receiver_id: seq}
+const MSG_SEQ_PREFIX: &str = "msg_seq";
+
+/// pushed message prefix, msg_pushed:server_id -> 1
+const MSG_PUSHED_PREFIX: &str = "msg_pushed";
+
+/// the seconds of keeping the handled state of a message,
+/// longer than the retention of the kafka topic to cover the redelivery
+const MSG_STATE_EXPIRE: i64 = 7 * 24 * 60 * 60;
+
 #[derive(Debug)]
 pub struct RedisCache {
     client: redis::Client,
     seq_step: i32,
     single_seq_exe_sha: String,
     group_seq_exe_sha: String,
+    unregister_gateway_exe_sha: String,
 }
 
 impl RedisCache {
@@ -41,12 +83,14 @@ impl RedisCache {
     pub fn new(client: redis::Client) -> Self {
         let seq_exe_sha = Self::single_script_load(&client);
         let group_seq_exe_sha = Self::group_script_load(&client);
+        let unregister_gateway_exe_sha = Self::unregister_gateway_script_load(&client);
         let seq_step = DEFAULT_SEQ_STEP;
         Self {
             client,
             single_seq_exe_sha: seq_exe_sha,
             seq_step,
             group_seq_exe_sha,
+            unregister_gateway_exe_sha,
         }
     }
     pub fn from_config(config: &Config) -> Self {
@@ -56,6 +100,7 @@ impl RedisCache {
         // init redis
         let single_seq_exe_sha = Self::single_script_load(&client);
         let group_seq_exe_sha = Self::group_script_load(&client);
+        let unregister_gateway_exe_sha = Self::unregister_gateway_script_load(&client);
         let mut seq_step = DEFAULT_SEQ_STEP;
         if config.redis.seq_step != 0 {
             seq_step = config.redis.seq_step;
@@ -65,6 +110,7 @@ impl RedisCache {
             seq_step,
             single_seq_exe_sha,
             group_seq_exe_sha,
+            unregister_gateway_exe_sha,
         }
     }
 
@@ -125,6 +171,81 @@ impl RedisCache {
             .load(&mut conn)
             .unwrap()
     }
+
+    /// delete the device record only if it still points to the gateway,
+    /// the user may have reconnected to another gateway before the old connection is closed,
+    /// the user is removed from the gateway's user set if none of the devices is left on it
+    fn unregister_gateway_script_load(client: &redis::Client) -> String {
+        let mut conn = client.get_connection().unwrap();
+
+        let script = r#"
+        if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+            return 0
+        end
+        redis.call('HDEL', KEYS[2], ARGV[1])
+        redis.call('HDEL', KEYS[1], ARGV[1])
+        for _, gateway in ipairs(redis.call('HVALS', KEYS[1])) do
+            if gateway == ARGV[2] then
+                return 1
+            end
+        end
+        -- no more devices of the user on the gateway
+        redis.call('SREM', KEYS[3], ARGV[3])
+        return 1
+        "#;
+        redis::Script::new(script)
+            .prepare_invoke()
+            .load(&mut conn)
+            .unwrap()
+    }
+
+    /// filter out the gateways whose lease is expired, they are crashed or killed
+    /// without unregistering the connections they held
+    async fn live_gateways<'a>(
+        &self,
+        conn: &mut MultiplexedConnection,
+        gateways: impl Iterator<Item = &'a String>,
+    ) -> Result<HashSet<String>, Error> {
+        let mut gateways: Vec<&String> = gateways.collect();
+        gateways.sort();
+        gateways.dedup();
+        if gateways.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let mut pipe = redis::pipe();
+        for gateway in &gateways {
+            pipe.exists(format!("{}:{}", GATEWAY_LEASE_PREFIX, gateway));
+        }
+        let exists: Vec<bool> = pipe.query_async(conn).await?;
+        Ok(gateways
+            .into_iter()
+            .zip(exists)
+            .filter(|(_, exists)| *exists)
+            .map(|(gateway, _)| gateway.clone())
+            .collect())
+    }
+
+    /// remove the device record if it still points to the gateway, see the unregister script
+    async fn unregister_device(
+        &self,
+        conn: &mut MultiplexedConnection,
+        user_id: &str,
+        device_id: &str,
+        gateway: &str,
+    ) -> Result<(), Error> {
+        let _: i64 = redis::cmd(EVALSHA)
+            .arg(&self.unregister_gateway_exe_sha)
+            .arg(3)
+            .arg(format!("{}:{}", USER_GATEWAY_PREFIX, user_id))
+            .arg(format!("{}:{}", USER_PLATFORM_PREFIX, user_id))
+            .arg(format!("{}:{}", GATEWAY_USERS_PREFIX, gateway))
+            .arg(device_id)
+            .arg(gateway)
+            .arg(user_id)
+            .query_async(conn)
+            .await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -242,6 +363,26 @@ impl Cache for RedisCache {
         Ok(seq)
     }
 
+    async fn increase_seq_batch(
+        &self,
+        user_ids: &[String],
+    ) -> Result<Vec<(i64, i64, bool)>, Error> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.cmd(EVALSHA)
+                .arg(&self.single_seq_exe_sha)
+                .arg(1)
+                .arg(format!("seq:{}", user_id))
+                .arg(self.seq_step);
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let seq = pipe.query_async(&mut conn).await?;
+        Ok(seq)
+    }
+
     async fn incr_send_seq(&self, user_id: &str) -> Result<(i64, i64, bool), Error> {
         // generate key
         let key = format!("send_seq:{}", user_id);
@@ -394,6 +535,374 @@ impl Cache for RedisCache {
         let result: i64 = conn.scard(USER_ONLINE_SET).await?;
         Ok(result)
     }
+
+    async fn register_gateway(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        platform: i32,
+        gateway: &str,
+    ) -> Result<(), Error> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = redis::pipe()
+            .atomic()
+            .hset(
+                format!("{}:{}", USER_GATEWAY_PREFIX, user_id),
+                device_id,
+                gateway,
+            )
+            .hset(
+                format!("{}:{}", USER_PLATFORM_PREFIX, user_id),
+                device_id,
+                platform,
+            )
+            .sadd(format!("{}:{}", GATEWAY_USERS_PREFIX, gateway), user_id)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn unregister_gateway(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        gateway: &str,
+    ) -> Result<(), Error> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        self.unregister_device(&mut conn, user_id, device_id, gateway)
+            .await
+    }
+
+    async fn query_user_gateways(
+        &self,
+        user_ids: &[String],
+    ) -> Result<HashMap<String, Vec<String>>, Error> {
+        if user_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.hgetall(format!("{}:{}", USER_GATEWAY_PREFIX, user_id));
+        }
+        let result: Vec<HashMap<String, String>> = pipe.query_async(&mut conn).await?;
+        let live = self
+            .live_gateways(&mut conn, result.iter().flat_map(|d| d.values()))
+            .await?;
+
+        let mut gateways = HashMap::with_capacity(user_ids.len());
+        for (user_id, devices) in user_ids.iter().zip(result) {
+            let mut list = Vec::with_capacity(devices.len());
+            for (device_id, gateway) in devices {
+                if live.contains(&gateway) {
+                    list.push(gateway);
+                } else {
+                    self.unregister_device(&mut conn, user_id, &device_id, &gateway)
+                        .await?;
+                }
+            }
+            if list.is_empty() {
+                continue;
+            }
+            // the user may connect to the same gateway with multiple devices
+            list.sort();
+            list.dedup();
+            gateways.insert(user_id.clone(), list);
+        }
+        Ok(gateways)
+    }
+
+    async fn query_user_devices(&self, user_id: &str) -> Result<Vec<(String, i32, String)>, Error> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let (gateways, platforms): (HashMap<String, String>, HashMap<String, i32>) = redis::pipe()
+            .hgetall(format!("{}:{}", USER_GATEWAY_PREFIX, user_id))
+            .hgetall(format!("{}:{}", USER_PLATFORM_PREFIX, user_id))
+            .query_async(&mut conn)
+            .await?;
+        let live = self.live_gateways(&mut conn, gateways.values()).await?;
+
+        let mut devices = Vec::with_capacity(gateways.len());
+        for (device_id, gateway) in gateways {
+            if !live.contains(&gateway) {
+                self.unregister_device(&mut conn, user_id, &device_id, &gateway)
+                    .await?;
+                continue;
+            }
+            let platform = platforms.get(&device_id).copied().unwrap_or_default();
+            devices.push((device_id, platform, gateway));
+        }
+        Ok(devices)
+    }
+
+    async fn refresh_gateway_lease(&self, gateway: &str, ttl: u64) -> Result<(), Error> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn
+            .set_ex(format!("{}:{}", GATEWAY_LEASE_PREFIX, gateway), 1, ttl)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_gateway(&self, gateway: &str) -> Result<(), Error> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let users_key = format!("{}:{}", GATEWAY_USERS_PREFIX, gateway);
+        let user_ids: Vec<String> = conn.smembers(&users_key).await?;
+        for user_id in user_ids {
+            let devices: HashMap<String, String> = conn
+                .hgetall(format!("{}:{}", USER_GATEWAY_PREFIX, user_id))
+                .await?;
+            for (device_id, _) in devices.into_iter().filter(|(_, g)| g == gateway) {
+                self.unregister_device(&mut conn, &user_id, &device_id, gateway)
+                    .await?;
+            }
+        }
+        let _: () = conn.del(&users_key).await?;
+        Ok(())
+    }
+
+    async fn set_presence(&self, user_id: &str, status: PresenceStatus) -> Result<(), Error> {
+        let key = format!("{}:{}", PRESENCE_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.hset(&key, "status", status as i32).await?;
+        Ok(())
+    }
+
+    async fn touch_presence(&self, user_id: &str, last_seen: i64) -> Result<(), Error> {
+        let key = format!("{}:{}", PRESENCE_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.hset(&key, "last_seen", last_seen).await?;
+        Ok(())
+    }
+
+    async fn set_hide_last_seen(&self, user_id: &str, hide: bool) -> Result<(), Error> {
+        let key = format!("{}:{}", PRESENCE_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.hset(&key, "hide_last_seen", hide).await?;
+        Ok(())
+    }
+
+    async fn query_presence(&self, user_ids: &[String]) -> Result<Vec<Presence>, Error> {
+        if user_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.hget(
+                format!("{}:{}", PRESENCE_PREFIX, user_id),
+                &["status", "last_seen", "hide_last_seen"],
+            );
+        }
+        let presences: Vec<(Option<i32>, Option<i64>, Option<bool>)> =
+            pipe.query_async(&mut conn).await?;
+
+        // the user is online if there is any connection held by a live gateway
+        let online = self.query_user_gateways(user_ids).await?;
+
+        let result = user_ids
+            .iter()
+            .zip(presences)
+            .map(|(user_id, (status, last_seen, hide))| {
+                let online = online.contains_key(user_id);
+                let status = if online {
+                    match status.and_then(|s| PresenceStatus::try_from(s).ok()) {
+                        // the status chosen by the user can not be offline
+                        None | Some(PresenceStatus::Offline) => PresenceStatus::Online,
+                        Some(status) => status,
+                    }
+                } else {
+                    PresenceStatus::Offline
+                };
+                let last_seen = if hide.unwrap_or_default() {
+                    0
+                } else {
+                    last_seen.unwrap_or_default()
+                };
+                Presence {
+                    user_id: user_id.clone(),
+                    status: status as i32,
+                    last_seen,
+                }
+            })
+            .collect();
+        Ok(result)
+    }
+
+    async fn incr_rate_counter(
+        &self,
+        user_id: &str,
+        category: &str,
+        window: u64,
+        count: u64,
+    ) -> Result<u64, Error> {
+        let window = window.max(1);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let index = now / window;
+        let key = format!("{}:{}:{}:{}", RATE_LIMIT_PREFIX, user_id, category, index);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let (count,): (u64,) = redis::pipe()
+            .atomic()
+            .incr(&key, count)
+            .expire(&key, window as i64)
+            .ignore()
+            .query_async(&mut conn)
+            .await?;
+        Ok(count)
+    }
+
+    async fn save_connect_ticket(
+        &self,
+        ticket: &str,
+        user_id: &str,
+        ttl: u64,
+    ) -> Result<(), Error> {
+        let key = format!("{}:{}", CONNECT_TICKET_PREFIX, ticket);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.set_ex(&key, user_id, ttl).await?;
+        Ok(())
+    }
+
+    async fn take_connect_ticket(&self, ticket: &str) -> Result<Option<String>, Error> {
+        let key = format!("{}:{}", CONNECT_TICKET_PREFIX, ticket);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let user_id: Option<String> = conn.get_del(&key).await?;
+        Ok(user_id)
+    }
+
+    async fn set_read_receipt_disabled(&self, user_id: &str, disabled: bool) -> Result<(), Error> {
+        let key = format!("{}:{}", PRIVACY_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = conn.hset(&key, "read_receipt_disabled", disabled).await?;
+        Ok(())
+    }
+
+    async fn is_read_receipt_disabled(&self, user_id: &str) -> Result<bool, Error> {
+        let key = format!("{}:{}", PRIVACY_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let disabled: Option<bool> = conn.hget(&key, "read_receipt_disabled").await?;
+        Ok(disabled.unwrap_or_default())
+    }
+
+    async fn query_read_receipt_disabled(&self, user_ids: &[String]) -> Result<Vec<String>, Error> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.hget(
+                format!("{}:{}", PRIVACY_PREFIX, user_id),
+                "read_receipt_disabled",
+            );
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let disabled: Vec<Option<bool>> = pipe.query_async(&mut conn).await?;
+        Ok(user_ids
+            .iter()
+            .zip(disabled)
+            .filter(|(_, disabled)| disabled.unwrap_or_default())
+            .map(|(user_id, _)| user_id.clone())
+            .collect())
+    }
+
+    async fn set_conversation_muted(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        muted: bool,
+    ) -> Result<(), Error> {
+        let key = format!("{}:{}", MUTE_PREFIX, user_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        if muted {
+            let _: () = conn.sadd(&key, conversation_id).await?;
+        } else {
+            let _: () = conn.srem(&key, conversation_id).await?;
+        }
+        Ok(())
+    }
+
+    async fn query_muted_users(
+        &self,
+        conversation_id: &str,
+        user_ids: &[String],
+    ) -> Result<Vec<String>, Error> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut pipe = redis::pipe();
+        for user_id in user_ids {
+            pipe.sismember(format!("{}:{}", MUTE_PREFIX, user_id), conversation_id);
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let muted: Vec<bool> = pipe.query_async(&mut conn).await?;
+        Ok(user_ids
+            .iter()
+            .zip(muted)
+            .filter(|(_, muted)| *muted)
+            .map(|(user_id, _)| user_id.clone())
+            .collect())
+    }
+
+    async fn save_msg_seq(&self, seqs: &[(String, Vec<GroupMemSeq>)]) -> Result<(), Error> {
+        if seqs.iter().all(|(_, seqs)| seqs.is_empty()) {
+            return Ok(());
+        }
+        let mut pipe = redis::pipe();
+        for (server_id, seqs) in seqs.iter().filter(|(_, seqs)| !seqs.is_empty()) {
+            let key = format!("{}:{}", MSG_SEQ_PREFIX, server_id);
+            let items: Vec<(&str, i64)> = seqs
+                .iter()
+                .map(|seq| (seq.mem_id.as_str(), seq.cur_seq))
+                .collect();
+            pipe.hset_multiple(&key, &items)
+                .ignore()
+                .expire(&key, MSG_STATE_EXPIRE)
+                .ignore();
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let _: () = pipe.atomic().query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn query_msg_seq(&self, server_ids: &[String]) -> Result<Vec<Vec<GroupMemSeq>>, Error> {
+        if server_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut pipe = redis::pipe();
+        for server_id in server_ids {
+            pipe.hgetall(format!("{}:{}", MSG_SEQ_PREFIX, server_id));
+        }
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let seqs: Vec<HashMap<String, i64>> = pipe.query_async(&mut conn).await?;
+        Ok(seqs
+            .into_iter()
+            .map(|seqs| {
+                seqs.into_iter()
+                    .map(|(mem_id, cur_seq)| GroupMemSeq {
+                        mem_id,
+                        cur_seq,
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+
+    async fn mark_msg_pushed(&self, server_id: &str) -> Result<bool, Error> {
+        let key = format!("{}:{}", MSG_PUSHED_PREFIX, server_id);
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let marked: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(MSG_STATE_EXPIRE)
+            .query_async(&mut conn)
+            .await?;
+        Ok(marked.is_some())
+    }
 }
 
 #[cfg(test)]
@@ -510,4 +1019,237 @@ mod tests {
         let result = cache.del_group_members(group_id).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_register_and_unregister_gateway() {
+        let user_id = "test";
+        let cache = TestRedis::from_db(7);
+        for gateway in ["127.0.0.1:50002", "127.0.0.1:50012"] {
+            cache.refresh_gateway_lease(gateway, 30).await.unwrap();
+        }
+        cache
+            .register_gateway(user_id, "desktop", 0, "127.0.0.1:50002")
+            .await
+            .unwrap();
+        cache
+            .register_gateway(user_id, "mobile", 1, "127.0.0.1:50012")
+            .await
+            .unwrap();
+
+        let users = vec![user_id.to_string(), "offline".to_string()];
+        let result = cache.query_user_gateways(&users).await.unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[user_id].len(), 2);
+
+        let mut devices = cache.query_user_devices(user_id).await.unwrap();
+        devices.sort();
+        assert_eq!(
+            devices,
+            vec![
+                ("desktop".to_string(), 0, "127.0.0.1:50002".to_string()),
+                ("mobile".to_string(), 1, "127.0.0.1:50012".to_string()),
+            ]
+        );
+
+        // the device belongs to another gateway, should not be removed
+        cache
+            .unregister_gateway(user_id, "mobile", "127.0.0.1:50002")
+            .await
+            .unwrap();
+        let result = cache.query_user_gateways(&users).await.unwrap();
+        assert_eq!(result[user_id].len(), 2);
+
+        cache
+            .unregister_gateway(user_id, "mobile", "127.0.0.1:50012")
+            .await
+            .unwrap();
+        let result = cache.query_user_gateways(&users).await.unwrap();
+        assert_eq!(result[user_id], vec!["127.0.0.1:50002".to_string()]);
+        let devices = cache.query_user_devices(user_id).await.unwrap();
+        assert_eq!(devices.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_gateway_lease() {
+        let user_id = "test";
+        let cache = TestRedis::from_db(12);
+        let users = vec![user_id.to_string()];
+        cache
+            .refresh_gateway_lease("127.0.0.1:50002", 30)
+            .await
+            .unwrap();
+        cache
+            .register_gateway(user_id, "desktop", 0, "127.0.0.1:50002")
+            .await
+            .unwrap();
+        // the gateway never takes the lease, as if it is crashed
+        cache
+            .register_gateway(user_id, "mobile", 1, "127.0.0.1:50012")
+            .await
+            .unwrap();
+
+        let result = cache.query_user_gateways(&users).await.unwrap();
+        assert_eq!(result[user_id], vec!["127.0.0.1:50002".to_string()]);
+        let devices = cache.query_user_devices(user_id).await.unwrap();
+        assert_eq!(devices.len(), 1);
+
+        // the gateway restarts and clears the records of the last run
+        cache.clear_gateway("127.0.0.1:50002").await.unwrap();
+        let result = cache.query_user_gateways(&users).await.unwrap();
+        assert!(result.is_empty());
+        let presence = cache.query_presence(&users).await.unwrap();
+        assert_eq!(presence[0].status, PresenceStatus::Offline as i32);
+    }
+
+    #[tokio::test]
+    async fn test_presence() {
+        let user_id = "presence";
+        let cache = TestRedis::from_db(6);
+        let users = vec![user_id.to_string()];
+
+        cache.touch_presence(user_id, 1000).await.unwrap();
+        let result = cache.query_presence(&users).await.unwrap();
+        assert_eq!(result[0].status, PresenceStatus::Offline as i32);
+        assert_eq!(result[0].last_seen, 1000);
+
+        cache
+            .refresh_gateway_lease("127.0.0.1:50002", 30)
+            .await
+            .unwrap();
+        cache
+            .register_gateway(user_id, "desktop", 0, "127.0.0.1:50002")
+            .await
+            .unwrap();
+        cache
+            .set_presence(user_id, PresenceStatus::Busy)
+            .await
+            .unwrap();
+        cache.set_hide_last_seen(user_id, true).await.unwrap();
+        let result = cache.query_presence(&users).await.unwrap();
+        assert_eq!(result[0].status, PresenceStatus::Busy as i32);
+        assert_eq!(result[0].last_seen, 0);
+    }
+
+    #[tokio::test]
+    async fn test_incr_rate_counter() {
+        let cache = TestRedis::from_db(5);
+        let user_id = "test";
+
+        let first = cache
+            .incr_rate_counter(user_id, "chat", 60, 1)
+            .await
+            .unwrap();
+        let second = cache
+            .incr_rate_counter(user_id, "chat", 60, 3)
+            .await
+            .unwrap();
+        // the window may be switched between the two calls
+        assert!(second == first + 3 || second == 3);
+        let other = cache
+            .incr_rate_counter(user_id, "signaling", 60, 1)
+            .await
+            .unwrap();
+        assert_eq!(other, 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_ticket() {
+        let cache = TestRedis::from_db(13);
+        cache
+            .save_connect_ticket("ticket", "user", 30)
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.take_connect_ticket("ticket").await.unwrap(),
+            Some("user".to_string())
+        );
+        // single use
+        assert!(cache.take_connect_ticket("ticket").await.unwrap().is_none());
+        assert!(
+            cache
+                .take_connect_ticket("unknown")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_receipt_disabled() {
+        let cache = TestRedis::from_db(14);
+        let user_id = "test";
+
+        assert!(!cache.is_read_receipt_disabled(user_id).await.unwrap());
+        cache
+            .set_read_receipt_disabled(user_id, true)
+            .await
+            .unwrap();
+        assert!(cache.is_read_receipt_disabled(user_id).await.unwrap());
+
+        let user_ids = vec![user_id.to_string(), "test_other".to_string()];
+        let disabled = cache.query_read_receipt_disabled(&user_ids).await.unwrap();
+        assert_eq!(disabled, vec![user_id.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_msg_seq() {
+        let cache = TestRedis::from_db(15);
+        let server_ids = vec!["test_msg_seq".to_string(), "test_msg_seq_2".to_string()];
+
+        let saved = cache.query_msg_seq(&server_ids).await.unwrap();
+        assert!(saved.iter().all(|seqs| seqs.is_empty()));
+        let seqs = vec![
+            GroupMemSeq::new("a".to_string(), 1, 0, false),
+            GroupMemSeq::new("b".to_string(), 5, 0, false),
+        ];
+        cache
+            .save_msg_seq(&[(server_ids[0].clone(), seqs.clone())])
+            .await
+            .unwrap();
+        let mut saved = cache.query_msg_seq(&server_ids).await.unwrap();
+        saved[0].sort_by(|a, b| a.mem_id.cmp(&b.mem_id));
+        assert_eq!(saved, vec![seqs, vec![]]);
+    }
+
+    #[tokio::test]
+    async fn test_increase_seq_batch() {
+        let cache = TestRedis::from_db(10);
+        let user_ids = vec!["a".to_string(), "b".to_string(), "a".to_string()];
+        let seq = cache.increase_seq_batch(&user_ids).await.unwrap();
+        let step = DEFAULT_SEQ_STEP as i64;
+        assert_eq!(
+            seq,
+            vec![(1, step, false), (1, step, false), (2, step, false)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mark_msg_pushed() {
+        let cache = TestRedis::from_db(4);
+        let server_id = "test_msg_pushed";
+
+        assert!(cache.mark_msg_pushed(server_id).await.unwrap());
+        assert!(!cache.mark_msg_pushed(server_id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_muted_users() {
+        let cache = TestRedis::from_db(11);
+        let users = vec!["a".to_string(), "b".to_string()];
+
+        cache
+            .set_conversation_muted("a", "group", true)
+            .await
+            .unwrap();
+        cache
+            .set_conversation_muted("b", "group", true)
+            .await
+            .unwrap();
+        cache
+            .set_conversation_muted("b", "group", false)
+            .await
+            .unwrap();
+        let muted = cache.query_muted_users("group", &users).await.unwrap();
+        assert_eq!(muted, vec!["a".to_string()]);
+    }
 }
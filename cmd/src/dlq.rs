@@ -0,0 +1,58 @@
+use clap::{value_parser, Arg, ArgMatches, Command};
+use tracing::error;
+
+use abi::config::Config;
+use msg_server::dead_letter::{self, DeadLetterRecord};
+
+const DEFAULT_LIMIT: &str = "100";
+
+/// inspect or replay the messages in the dead letter topic
+pub fn dlq_command() -> Command {
+    let limit = Arg::new("limit")
+        .short('n')
+        .long("limit")
+        .value_name("LIMIT")
+        .value_parser(value_parser!(usize))
+        .default_value(DEFAULT_LIMIT)
+        .help("The max count of the messages");
+    Command::new("dlq")
+        .about("Inspect or replay the dead lettered messages")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("inspect")
+                .about("Print the messages which are not replayed")
+                .arg(limit.clone()),
+        )
+        .subcommand(
+            Command::new("replay")
+                .about("Send the messages back to the chat topic")
+                .arg(limit),
+        )
+}
+
+pub async fn dlq(config: &Config, matches: &ArgMatches) {
+    let result = match matches.subcommand() {
+        Some(("inspect", args)) => {
+            dead_letter::inspect(&config.kafka, *args.get_one("limit").unwrap())
+                .await
+                .map(|records| records.iter().for_each(print_record))
+        }
+        Some(("replay", args)) => {
+            dead_letter::replay(&config.kafka, *args.get_one("limit").unwrap())
+                .await
+                .map(|count| println!("replayed {} messages", count))
+        }
+        _ => return,
+    };
+    if let Err(e) = result {
+        error!("dead letter command error: {:?}", e);
+    }
+}
+
+fn print_record(record: &DeadLetterRecord) {
+    println!(
+        "{}:{}@{} failed at {}: {}",
+        record.topic, record.partition, record.offset, record.failed_at, record.reason
+    );
+    println!("{}", String::from_utf8_lossy(&record.payload));
+}
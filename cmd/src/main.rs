@@ -1,3 +1,5 @@
+#[cfg(feature = "kafka")]
+mod dlq;
 mod load_seq;
 
 use clap::{command, Arg};
@@ -6,6 +8,8 @@ use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::time::FormatTime;
 
 use abi::config::{Component, Config};
+#[cfg(feature = "kafka")]
+use dlq::{dlq, dlq_command};
 use load_seq::load_seq;
 use msg_gateway::ws_server::WsServer;
 
@@ -28,7 +32,7 @@ async fn main() {
     // consumer rely on db and pusher rpc server;
 
     // get configuration path
-    let matches = command!()
+    let command = command!()
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about(env!("CARGO_PKG_DESCRIPTION"))
@@ -39,8 +43,10 @@ async fn main() {
                 .value_name("CONFIGURATION")
                 .default_value(DEFAULT_CONFIG_PATH)
                 .help("Set the configuration path"),
-        )
-        .get_matches();
+        );
+    #[cfg(feature = "kafka")]
+    let command = command.subcommand(dlq_command());
+    let matches = command.get_matches();
     let default_config = DEFAULT_CONFIG_PATH.to_string();
     let configuration = matches
         .get_one::<String>("configuration")
@@ -69,6 +75,12 @@ async fn main() {
             .with_timer(LocalTimer)
             .init();
     }
+    #[cfg(feature = "kafka")]
+    if let Some(("dlq", args)) = matches.subcommand() {
+        dlq(&config, args).await;
+        return;
+    }
+
     // check if redis need to load seq
     load_seq(&config).await;
 
@@ -88,7 +100,9 @@ async fn start_all(config: Config) {
     });
 
     // start cleaner
-    db::clean_receive_box(&config).await;
+    db::clean_receive_box(&config)
+        .await
+        .expect("cleaner can't start");
 
     // start api server
     let cloned_config = config.clone();
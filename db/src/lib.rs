@@ -1,9 +1,10 @@
 use friend::FriendRepo;
 use group::GroupStoreRepo;
+use push::PushTokenRepo;
 use seq::SeqRepo;
 use tracing::info;
 
-use abi::{config::Config, message::MsgType};
+use abi::{config::Config, errors::Error, message::MsgType};
 use user::UserRepo;
 
 mod mongodb;
@@ -12,6 +13,7 @@ mod postgres;
 pub mod friend;
 pub mod group;
 pub mod message;
+pub mod push;
 // pub mod rpc;
 pub mod seq;
 pub mod user;
@@ -30,6 +32,7 @@ pub struct DbRepo {
     pub user: Box<dyn UserRepo>,
     pub friend: Box<dyn FriendRepo>,
     pub seq: Box<dyn SeqRepo>,
+    pub push: Box<dyn PushTokenRepo>,
 }
 
 impl DbRepo {
@@ -41,6 +44,7 @@ impl DbRepo {
         let user = Box::new(postgres::PostgresUser::new(pool.clone(), seq_step));
         let friend = Box::new(postgres::PostgresFriend::new(pool.clone()));
         let group = Box::new(postgres::PostgresGroup::new(pool.clone()));
+        let push = Box::new(postgres::PostgresPushToken::new(pool.clone()));
         let seq = Box::new(postgres::PostgresSeq::new(pool, seq_step));
         Self {
             msg,
@@ -48,19 +52,20 @@ impl DbRepo {
             user,
             friend,
             seq,
+            push,
         }
     }
 }
 
-pub async fn msg_rec_box_repo(config: &Config) -> Arc<dyn MsgRecBoxRepo> {
-    Arc::new(mongodb::MsgBox::from_config(config).await)
+pub async fn msg_rec_box_repo(config: &Config) -> Result<Arc<dyn MsgRecBoxRepo>, Error> {
+    Ok(Arc::new(mongodb::MsgBox::from_config(config).await?))
 }
 
-pub async fn msg_rec_box_cleaner(config: &Config) -> Arc<dyn MsgRecBoxCleaner> {
-    Arc::new(mongodb::MsgBox::from_config(config).await)
+pub async fn msg_rec_box_cleaner(config: &Config) -> Result<Arc<dyn MsgRecBoxCleaner>, Error> {
+    Ok(Arc::new(mongodb::MsgBox::from_config(config).await?))
 }
 
-pub async fn clean_receive_box(config: &Config) {
+pub async fn clean_receive_box(config: &Config) -> Result<(), Error> {
     let types: Vec<i32> = config
         .db
         .mongodb
@@ -72,10 +77,11 @@ pub async fn clean_receive_box(config: &Config) {
         .collect();
     let period = config.db.mongodb.clean.period;
 
-    let msg_box = msg_rec_box_cleaner(config).await;
+    let msg_box = msg_rec_box_cleaner(config).await?;
     info!(
         "clean receive box task started, and the period is {period}s; the except types is {:?}",
         types
     );
     msg_box.clean_receive_box(period, types);
+    Ok(())
 }
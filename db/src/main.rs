@@ -13,7 +13,9 @@ async fn main() {
     let config = Config::load("config.yml").unwrap();
 
     // start cleaner
-    db::clean_receive_box(&config).await;
+    db::clean_receive_box(&config)
+        .await
+        .expect("cleaner can't start");
 
     // start rpc service
     // DbRpcService::start(&config).await;
@@ -4,13 +4,16 @@ use async_trait::async_trait;
 use tokio::sync::mpsc;
 
 use abi::errors::Error;
-use abi::message::{GroupMemSeq, Msg};
+use abi::message::{GroupMemSeq, Msg, ReadCursor};
 
 /// face to postgres db
 #[async_trait]
 pub trait MsgStoreRepo: Sync + Send + Debug {
     /// save message to db
     async fn save_message(&self, message: Msg) -> Result<(), Error>;
+
+    /// save messages with multi-row inserts, the saved messages are skipped
+    async fn save_messages(&self, messages: &[Msg]) -> Result<(), Error>;
 }
 
 /// message receive box
@@ -25,6 +28,10 @@ pub trait MsgRecBoxRepo: Sync + Send + Debug {
     /// need the group members id
     async fn save_group_msg(&self, message: Msg, members: Vec<GroupMemSeq>) -> Result<(), Error>;
 
+    /// save the messages with one write, the group messages are copied for the members,
+    /// the members of the single messages are empty
+    async fn save_messages(&self, messages: Vec<(Msg, Vec<GroupMemSeq>)>) -> Result<(), Error>;
+
     async fn delete_message(&self, message_id: &str) -> Result<(), Error>;
 
     async fn delete_messages(&self, user_id: &str, msg_seq: Vec<i64>) -> Result<(), Error>;
@@ -41,6 +48,15 @@ pub trait MsgRecBoxRepo: Sync + Send + Debug {
         end: i64,
     ) -> Result<mpsc::Receiver<Result<Msg, Error>>, Error>;
 
+    /// the messages sent by the user in the send sequence range, ordered by send sequence,
+    /// the seq of them is set to 0 like `get_msgs`
+    async fn get_sent_messages_stream(
+        &self,
+        user_id: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<mpsc::Receiver<Result<Msg, Error>>, Error>;
+
     #[deprecated]
     async fn get_messages(&self, user_id: &str, start: i64, end: i64) -> Result<Vec<Msg>, Error>;
 
@@ -53,8 +69,22 @@ pub trait MsgRecBoxRepo: Sync + Send + Debug {
         rec_end: i64,
     ) -> Result<Vec<Msg>, Error>;
 
+    /// the messages received by the user with the sequences
+    async fn get_messages_by_seq(&self, user_id: &str, msg_seq: &[i64]) -> Result<Vec<Msg>, Error>;
+
     /// update message read status by user id and message sequence
     async fn msg_read(&self, user_id: &str, msg_seq: &[i64]) -> Result<(), Error>;
+
+    /// move the read cursor of the conversation forward,
+    /// return false if the saved cursor is not behind it
+    async fn save_read_cursor(&self, cursor: &ReadCursor) -> Result<bool, Error>;
+
+    /// the read cursors of the user updated after the timestamp
+    async fn get_read_cursors(&self, user_id: &str, since: i64) -> Result<Vec<ReadCursor>, Error>;
+
+    /// the members whose read cursor of the group reaches the send time
+    async fn get_group_readers(&self, group_id: &str, send_time: i64)
+    -> Result<Vec<String>, Error>;
 }
 
 pub trait MsgRecBoxCleaner: Sync + Send {
@@ -1,18 +1,19 @@
 use std::fmt::Debug;
 
 use async_trait::async_trait;
-use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::error::{BulkWriteFailure, ErrorKind, WriteError, WriteFailure};
+use mongodb::options::{AggregateOptions, FindOptions, IndexOptions, InsertManyOptions};
 use mongodb::{
     Client, Collection, Database, IndexModel,
     bson::{Document, doc},
 };
 use tokio::sync::mpsc;
 use tonic::codegen::tokio_stream::StreamExt;
-use tracing::log::debug;
+use tracing::log::{debug, info};
 
 use abi::config::Config;
-use abi::errors::Error;
-use abi::message::{GroupMemSeq, Msg};
+use abi::errors::{self, Error};
+use abi::message::{GroupMemSeq, Msg, ReadCursor};
 
 use crate::message::{MsgRecBoxCleaner, MsgRecBoxRepo};
 use crate::mongodb::utils::to_doc;
@@ -25,74 +26,207 @@ use crate::mongodb::utils::to_doc;
 pub struct MsgBox {
     /// for message box
     mb: Collection<Document>,
+    /// the read cursor of each conversation of the users
+    rc: Collection<Document>,
 }
 
 /// for all users single message receive box
 const COLL_SINGLE_BOX: &str = "single_msg_box";
 
+const COLL_READ_CURSOR: &str = "read_cursor";
+
+/// the error code of violating the unique index
+const DUPLICATE_KEY: i32 = 11000;
+
+/// the unique index of the message box, the messages are deduplicated before it is created
+const BOX_UNIQUE_INDEX: &str = "server_id_1_receiver_id_1";
+
 #[allow(dead_code)]
 impl MsgBox {
-    pub async fn new(db: Database) -> Self {
+    pub async fn new(db: Database) -> Result<Self, Error> {
         let mb = db.collection(COLL_SINGLE_BOX);
-        Self { mb }
+        Self::create_box_index(&mb).await?;
+        let rc = db.collection(COLL_READ_CURSOR);
+        Self::create_cursor_index(&rc).await;
+        Ok(Self { mb, rc })
     }
-    pub async fn from_config(config: &Config) -> Self {
+    pub async fn from_config(config: &Config) -> Result<Self, Error> {
         let db = Client::with_uri_str(config.db.mongodb.url())
             .await
             .unwrap()
             .database(&config.db.mongodb.database);
         let mb = db.collection(COLL_SINGLE_BOX);
+        Self::create_box_index(&mb).await?;
+
+        let rc = db.collection(COLL_READ_CURSOR);
+        Self::create_cursor_index(&rc).await;
+
+        Ok(Self { mb, rc })
+    }
 
+    async fn create_box_index(mb: &Collection<Document>) -> Result<(), Error> {
         // create server_id index
         let index_model = IndexModel::builder()
             .keys(doc! {"receiver_id": 1, "seq":1})
             .options(IndexOptions::builder().unique(false).build())
             .build();
-        mb.create_index(index_model, None).await.unwrap();
+        mb.create_index(index_model, None).await?;
         debug!("create [receiver_id, seq] index for message box");
 
         let index_model = IndexModel::builder()
             .keys(doc! {"send_id": 1, "send_seq":1})
             .options(IndexOptions::builder().unique(false).build())
             .build();
-        mb.create_index(index_model, None).await.unwrap();
+        mb.create_index(index_model, None).await?;
         debug!("create [send_id, send_seq] index for message box");
 
-        Self { mb }
+        // the messages saved by the redelivery before the unique index break the creation
+        let indexes = mb.list_index_names().await?;
+        if !indexes.iter().any(|name| name == BOX_UNIQUE_INDEX) {
+            let removed = Self::remove_duplicate_messages(mb).await?;
+            info!("removed {} duplicate messages from message box", removed);
+        }
+
+        // one copy of a message for each receiver, the group members share the server id,
+        // the redelivered messages are rejected by it
+        let index_model = IndexModel::builder()
+            .keys(doc! {"server_id": 1, "receiver_id": 1})
+            .options(
+                IndexOptions::builder()
+                    .name(BOX_UNIQUE_INDEX.to_string())
+                    .unique(true)
+                    .build(),
+            )
+            .build();
+        mb.create_index(index_model, None).await.map_err(|e| {
+            Error::new(
+                errors::ErrorKind::MongoDbOperateError,
+                format!(
+                    "create unique [server_id, receiver_id] index for message box: {}",
+                    e
+                ),
+                e,
+            )
+        })?;
+        debug!("create [server_id, receiver_id] index for message box");
+        Ok(())
+    }
+
+    /// keep the first saved copy of the message for each receiver,
+    /// return the count of the removed copies
+    async fn remove_duplicate_messages(mb: &Collection<Document>) -> Result<u64, Error> {
+        let pipeline = vec![
+            doc! {"$sort": {"_id": 1}},
+            doc! {"$group": {
+                "_id": {"server_id": "$server_id", "receiver_id": "$receiver_id"},
+                "ids": {"$push": "$_id"},
+                "count": {"$sum": 1},
+            }},
+            doc! {"$match": {"count": {"$gt": 1}}},
+        ];
+        let option = AggregateOptions::builder().allow_disk_use(true).build();
+        let mut cursor = mb.aggregate(pipeline, option).await?;
+        let mut removed = 0;
+        while let Some(group) = cursor.next().await {
+            let group = group?;
+            let copies = group.get_array("ids")?[1..].to_vec();
+            let result = mb.delete_many(doc! {"_id": {"$in": copies}}, None).await?;
+            removed += result.deleted_count;
+        }
+        Ok(removed)
+    }
+
+    /// one cursor for each conversation of the user
+    async fn create_cursor_index(rc: &Collection<Document>) {
+        let index_model = IndexModel::builder()
+            .keys(doc! {"user_id": 1, "conversation_id": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        rc.create_index(index_model, None).await.unwrap();
+        debug!("create [user_id, conversation_id] index for read cursor");
+
+        // count the readers of the group messages
+        let index_model = IndexModel::builder()
+            .keys(doc! {"conversation_id": 1, "last_send_time": 1})
+            .options(IndexOptions::builder().unique(false).build())
+            .build();
+        rc.create_index(index_model, None).await.unwrap();
+        debug!("create [conversation_id, last_send_time] index for read cursor");
+    }
+
+    /// read the cursor in a separate task, the bounded channel holds the cursor
+    /// until the receiver consumes the messages
+    async fn stream(
+        &self,
+        query: Document,
+        option: FindOptions,
+        sent: bool,
+    ) -> Result<mpsc::Receiver<Result<Msg, Error>>, Error> {
+        let mut cursor = self.mb.find(query, Some(option)).await?;
+        let (tx, rx) = mpsc::channel(100);
+        tokio::spawn(async move {
+            while let Some(result) = cursor.next().await {
+                let result = result
+                    .map_err(Error::from)
+                    .and_then(Msg::try_from)
+                    .map(|mut msg| {
+                        if sent {
+                            msg.seq = 0;
+                        }
+                        msg
+                    });
+                if tx.send(result).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(rx)
     }
 }
 
 #[async_trait]
 impl MsgRecBoxRepo for MsgBox {
     async fn save_message(&self, message: &Msg) -> Result<(), Error> {
-        self.mb.insert_one(to_doc(message)?, None).await?;
+        match self.mb.insert_one(to_doc(message)?, None).await {
+            // saved by the previous delivery
+            Err(e) if !is_duplicate_key(&e) => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
 
-        Ok(())
+    async fn save_group_msg(&self, message: Msg, members: Vec<GroupMemSeq>) -> Result<(), Error> {
+        self.save_messages(vec![(message, members)]).await
     }
 
-    async fn save_group_msg(
-        &self,
-        mut message: Msg,
-        members: Vec<GroupMemSeq>,
-    ) -> Result<(), Error> {
-        let mut messages = Vec::with_capacity(members.len() + 1);
-        // save message for sender
-        messages.push(to_doc(&message)?);
+    async fn save_messages(&self, messages: Vec<(Msg, Vec<GroupMemSeq>)>) -> Result<(), Error> {
+        let mut docs = Vec::with_capacity(messages.len());
+        for (mut message, members) in messages {
+            // the receiver of single message, or the sender of group message
+            docs.push(to_doc(&message)?);
+
+            // reset message send_seq
+            message.send_seq = 0;
 
-        // reset message send_seq
-        message.send_seq = 0;
+            // modify message receiver id
+            for seq in members {
+                // increase members sequence
+                message.seq = seq.cur_seq;
 
-        // modify message receiver id
-        for seq in members {
-            // increase members sequence
-            message.seq = seq.cur_seq;
+                message.receiver_id = seq.mem_id;
 
-            message.receiver_id = seq.mem_id;
+                docs.push(to_doc(&message)?);
+            }
+        }
+        if docs.is_empty() {
+            return Ok(());
+        }
 
-            messages.push(to_doc(&message)?);
+        // keep inserting the others if some of them are saved by the previous delivery
+        let option = InsertManyOptions::builder().ordered(false).build();
+        match self.mb.insert_many(docs, option).await {
+            Err(e) if !is_duplicate_key(&e) => Err(e.into()),
+            _ => Ok(()),
         }
-        self.mb.insert_many(messages, None).await?;
-        Ok(())
     }
 
     async fn delete_message(&self, message_id: &str) -> Result<(), Error> {
@@ -136,24 +270,29 @@ impl MsgRecBoxRepo for MsgBox {
         // sort by seq
         let option = FindOptions::builder().sort(Some(doc! {"seq": 1})).build();
 
-        // query
-        let mut cursor = self.mb.find(query, Some(option)).await?;
-        let (tx, rx) = mpsc::channel(100);
-        while let Some(result) = cursor.next().await {
-            match result {
-                Ok(doc) => {
-                    if tx.send(Ok(Msg::try_from(doc)?)).await.is_err() {
-                        break;
-                    }
-                }
-                Err(e) => {
-                    if tx.send(Err(e.into())).await.is_err() {
-                        break;
-                    }
-                }
+        self.stream(query, option, false).await
+    }
+
+    async fn get_sent_messages_stream(
+        &self,
+        user_id: &str,
+        start: i64,
+        end: i64,
+    ) -> Result<mpsc::Receiver<Result<Msg, Error>>, Error> {
+        let query = doc! {
+            "send_id": user_id,
+            "send_seq": {
+                "$gte": start,
+                "$lte": end
             }
-        }
-        Ok(rx)
+        };
+
+        // sort by send seq
+        let option = FindOptions::builder()
+            .sort(Some(doc! {"send_seq": 1}))
+            .build();
+
+        self.stream(query, option, true).await
     }
 
     async fn get_messages(&self, user_id: &str, start: i64, end: i64) -> Result<Vec<Msg>, Error> {
@@ -237,6 +376,19 @@ impl MsgRecBoxRepo for MsgBox {
         Ok(messages)
     }
 
+    async fn get_messages_by_seq(&self, user_id: &str, msg_seq: &[i64]) -> Result<Vec<Msg>, Error> {
+        if msg_seq.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = doc! {"receiver_id": user_id, "seq": {"$in": msg_seq}};
+        let mut cursor = self.mb.find(query, None).await?;
+        let mut messages = Vec::with_capacity(msg_seq.len());
+        while let Some(result) = cursor.next().await {
+            messages.push(Msg::try_from(result?)?);
+        }
+        Ok(messages)
+    }
+
     async fn msg_read(&self, user_id: &str, msg_seq: &[i64]) -> Result<(), Error> {
         if msg_seq.is_empty() {
             return Ok(());
@@ -246,6 +398,90 @@ impl MsgRecBoxRepo for MsgBox {
         self.mb.update_many(query, update, None).await?;
         Ok(())
     }
+
+    async fn save_read_cursor(&self, cursor: &ReadCursor) -> Result<bool, Error> {
+        let query = doc! {
+            "user_id": &cursor.user_id,
+            "conversation_id": &cursor.conversation_id,
+            "seq": {"$lt": cursor.seq}
+        };
+        let update = doc! {"$set": {
+            "seq": cursor.seq,
+            "update_time": cursor.update_time,
+            "last_send_time": cursor.last_send_time
+        }};
+        if self.rc.update_one(query, update, None).await?.matched_count > 0 {
+            return Ok(true);
+        }
+
+        // the cursor is not saved yet, or it is ahead already
+        let document = doc! {
+            "user_id": &cursor.user_id,
+            "conversation_id": &cursor.conversation_id,
+            "seq": cursor.seq,
+            "update_time": cursor.update_time,
+            "last_send_time": cursor.last_send_time,
+        };
+        match self.rc.insert_one(document, None).await {
+            Ok(_) => Ok(true),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn get_read_cursors(&self, user_id: &str, since: i64) -> Result<Vec<ReadCursor>, Error> {
+        let query = doc! {"user_id": user_id, "update_time": {"$gt": since}};
+        let mut cursor = self.rc.find(query, None).await?;
+        let mut cursors = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let doc = result?;
+            cursors.push(ReadCursor {
+                user_id: doc.get_str("user_id").unwrap_or_default().to_string(),
+                conversation_id: doc
+                    .get_str("conversation_id")
+                    .unwrap_or_default()
+                    .to_string(),
+                seq: doc.get_i64("seq").unwrap_or_default(),
+                update_time: doc.get_i64("update_time").unwrap_or_default(),
+                last_send_time: doc.get_i64("last_send_time").unwrap_or_default(),
+            });
+        }
+        Ok(cursors)
+    }
+
+    async fn get_group_readers(
+        &self,
+        group_id: &str,
+        send_time: i64,
+    ) -> Result<Vec<String>, Error> {
+        let query = doc! {"conversation_id": group_id, "last_send_time": {"$gte": send_time}};
+        let option = FindOptions::builder()
+            .projection(doc! {"user_id": 1})
+            .build();
+        let mut cursor = self.rc.find(query, Some(option)).await?;
+        let mut readers = Vec::new();
+        while let Some(result) = cursor.next().await {
+            if let Ok(user_id) = result?.get_str("user_id") {
+                readers.push(user_id.to_string());
+            }
+        }
+        Ok(readers)
+    }
+}
+
+/// all the failed writes violate the unique index
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    match e.kind.as_ref() {
+        ErrorKind::Write(WriteFailure::WriteError(WriteError { code, .. })) => {
+            *code == DUPLICATE_KEY
+        }
+        ErrorKind::BulkWrite(BulkWriteFailure {
+            write_errors: Some(errors),
+            write_concern_error: None,
+            ..
+        }) => errors.iter().all(|error| error.code == DUPLICATE_KEY),
+        _ => false,
+    }
 }
 
 impl MsgRecBoxCleaner for MsgBox {
@@ -315,7 +551,7 @@ mod tests {
                 &config.db.mongodb.password,
             )
             .await;
-            let msg_box = MsgBox::new(tdb.database().await).await;
+            let msg_box = MsgBox::new(tdb.database().await).await.unwrap();
             Self {
                 box_: msg_box,
                 _tdb: tdb,
@@ -372,6 +608,7 @@ mod tests {
             avatar: "".to_string(),
             nickname: "".to_string(),
             related_msg_id: None,
+            device_id: "".to_string(),
         }
     }
     #[tokio::test]
@@ -407,4 +644,125 @@ mod tests {
         let msg = msg_box.get_message(&msg_id[2]).await.unwrap();
         assert!(msg.is_none());
     }
+
+    #[tokio::test]
+    async fn mongodb_redelivered_message_works() {
+        let msg_box = TestConfig::new().await;
+        let msg = get_test_msg("123".to_string());
+        msg_box.save_message(&msg).await.unwrap();
+        msg_box.save_message(&msg).await.unwrap();
+        let count = msg_box
+            .mb
+            .count_documents(doc! {"server_id": "123"}, None)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let mut msg = get_test_msg("124".to_string());
+        msg.receiver_id = "group".to_string();
+        let members = vec![GroupMemSeq::new("222".to_string(), 1, 0, false)];
+        msg_box
+            .save_group_msg(msg.clone(), members.clone())
+            .await
+            .unwrap();
+        // the saved copies are skipped, the others are inserted
+        let mut members = members;
+        members.push(GroupMemSeq::new("333".to_string(), 1, 0, false));
+        msg_box.save_group_msg(msg, members).await.unwrap();
+        let count = msg_box
+            .mb
+            .count_documents(doc! {"server_id": "124"}, None)
+            .await
+            .unwrap();
+        assert_eq!(count, 3);
+    }
+
+    #[tokio::test]
+    async fn mongodb_remove_duplicate_messages_works() {
+        let msg_box = TestConfig::new().await;
+        // the copies saved before the unique index is created
+        msg_box.mb.drop_index(BOX_UNIQUE_INDEX, None).await.unwrap();
+        let msg = get_test_msg("123".to_string());
+        let docs = vec![to_doc(&msg).unwrap(), to_doc(&msg).unwrap()];
+        msg_box.mb.insert_many(docs, None).await.unwrap();
+
+        MsgBox::create_box_index(&msg_box.mb).await.unwrap();
+        let count = msg_box
+            .mb
+            .count_documents(doc! {"server_id": "123"}, None)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn mongodb_save_messages_works() {
+        let msg_box = TestConfig::new().await;
+        let single = get_test_msg("123".to_string());
+        let mut group = get_test_msg("124".to_string());
+        group.receiver_id = "group".to_string();
+        let members = vec![
+            GroupMemSeq::new("222".to_string(), 1, 0, false),
+            GroupMemSeq::new("333".to_string(), 2, 0, false),
+        ];
+        msg_box
+            .save_messages(vec![(single, vec![]), (group, members)])
+            .await
+            .unwrap();
+
+        let count = msg_box.mb.count_documents(None, None).await.unwrap();
+        assert_eq!(count, 4);
+        let count = msg_box
+            .mb
+            .count_documents(doc! {"receiver_id": "333", "seq": 2}, None)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn mongodb_read_cursor_works() {
+        let msg_box = TestConfig::new().await;
+        let mut cursor = ReadCursor {
+            user_id: "111".to_string(),
+            conversation_id: "222".to_string(),
+            seq: 10,
+            update_time: 100,
+            last_send_time: 1000,
+        };
+        assert!(msg_box.save_read_cursor(&cursor).await.unwrap());
+
+        // the cursor only moves forward
+        cursor.seq = 5;
+        cursor.update_time = 200;
+        assert!(!msg_box.save_read_cursor(&cursor).await.unwrap());
+        cursor.seq = 20;
+        cursor.update_time = 300;
+        assert!(msg_box.save_read_cursor(&cursor).await.unwrap());
+
+        let cursors = msg_box.get_read_cursors("111", 0).await.unwrap();
+        assert_eq!(cursors, vec![cursor]);
+        let cursors = msg_box.get_read_cursors("111", 300).await.unwrap();
+        assert!(cursors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn mongodb_group_readers_works() {
+        let msg_box = TestConfig::new().await;
+        for (user_id, last_send_time) in [("111", 100), ("222", 200)] {
+            let cursor = ReadCursor {
+                user_id: user_id.to_string(),
+                conversation_id: "group".to_string(),
+                seq: 1,
+                update_time: 1,
+                last_send_time,
+            };
+            msg_box.save_read_cursor(&cursor).await.unwrap();
+        }
+
+        let readers = msg_box.get_group_readers("group", 150).await.unwrap();
+        assert_eq!(readers, vec!["222".to_string()]);
+        let readers = msg_box.get_group_readers("group", 100).await.unwrap();
+        assert_eq!(readers.len(), 2);
+    }
 }
@@ -1,11 +1,14 @@
 use async_trait::async_trait;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, QueryBuilder};
 
 use abi::errors::Error;
 use abi::message::Msg;
 
 use crate::message::MsgStoreRepo;
 
+/// postgres limits the bind parameters of a statement to 65535
+const MAX_ROWS_PER_INSERT: usize = 1000;
+
 #[derive(Debug)]
 pub struct PostgresMessage {
     pool: PgPool,
@@ -40,4 +43,27 @@ impl MsgStoreRepo for PostgresMessage {
         .await?;
         Ok(())
     }
+
+    async fn save_messages(&self, messages: &[Msg]) -> Result<(), Error> {
+        for chunk in messages.chunks(MAX_ROWS_PER_INSERT) {
+            let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO messages
+                 (local_id, server_id, send_id, receiver_id, msg_type, content_type, content, send_time, platform) ",
+            );
+            builder.push_values(chunk, |mut row, message| {
+                row.push_bind(&message.local_id)
+                    .push_bind(&message.server_id)
+                    .push_bind(&message.send_id)
+                    .push_bind(&message.receiver_id)
+                    .push_bind(message.msg_type)
+                    .push_bind(message.content_type)
+                    .push_bind(&message.content)
+                    .push_bind(message.send_time)
+                    .push_bind(message.platform);
+            });
+            builder.push(" ON CONFLICT DO NOTHING");
+            builder.build().execute(&self.pool).await?;
+        }
+        Ok(())
+    }
 }
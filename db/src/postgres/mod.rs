@@ -1,11 +1,13 @@
 mod friend;
 mod group;
 mod message;
+mod push;
 mod seq;
 mod user;
 
 pub(crate) use friend::*;
 pub(crate) use group::*;
 pub(crate) use message::*;
+pub(crate) use push::*;
 pub(crate) use seq::*;
 pub(crate) use user::*;
@@ -0,0 +1,58 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+
+use abi::errors::Error;
+use abi::message::PushToken;
+
+use crate::push::PushTokenRepo;
+
+#[derive(Debug)]
+pub struct PostgresPushToken {
+    pool: PgPool,
+}
+
+impl PostgresPushToken {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PushTokenRepo for PostgresPushToken {
+    async fn save_push_token(&self, token: &PushToken) -> Result<(), Error> {
+        sqlx::query(
+            "INSERT INTO push_tokens (user_id, device_id, platform, token, update_time)
+             VALUES ($1, $2, $3, $4, $5)
+             ON CONFLICT (user_id, device_id)
+             DO UPDATE SET platform = EXCLUDED.platform, token = EXCLUDED.token, update_time = EXCLUDED.update_time",
+        )
+        .bind(&token.user_id)
+        .bind(&token.device_id)
+        .bind(token.platform)
+        .bind(&token.token)
+        .bind(token.update_time)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_push_token(&self, user_id: &str, device_id: &str) -> Result<(), Error> {
+        sqlx::query("DELETE FROM push_tokens WHERE user_id = $1 AND device_id = $2")
+            .bind(user_id)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_push_tokens(&self, user_ids: &[String]) -> Result<Vec<PushToken>, Error> {
+        if user_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        let tokens = sqlx::query_as("SELECT * FROM push_tokens WHERE user_id = ANY($1)")
+            .bind(user_ids)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(tokens)
+    }
+}
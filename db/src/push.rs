@@ -0,0 +1,18 @@
+use std::fmt::Debug;
+
+use async_trait::async_trait;
+
+use abi::errors::Error;
+use abi::message::PushToken;
+
+/// the push tokens of the devices, one token for each device of the user
+#[async_trait]
+pub trait PushTokenRepo: Send + Sync + Debug {
+    /// replace the token of the device if it exists
+    async fn save_push_token(&self, token: &PushToken) -> Result<(), Error>;
+
+    async fn delete_push_token(&self, user_id: &str, device_id: &str) -> Result<(), Error>;
+
+    /// the tokens of all the devices of the users
+    async fn get_push_tokens(&self, user_ids: &[String]) -> Result<Vec<PushToken>, Error>;
+}
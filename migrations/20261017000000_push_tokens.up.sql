@@ -0,0 +1,8 @@
+CREATE TABLE push_tokens (
+    user_id     VARCHAR NOT NULL,
+    device_id   VARCHAR NOT NULL,
+    platform    INT     NOT NULL DEFAULT 0,
+    token       VARCHAR NOT NULL,
+    update_time BIGINT  NOT NULL,
+    PRIMARY KEY (user_id, device_id)
+);
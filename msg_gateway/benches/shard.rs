@@ -0,0 +1,83 @@
+//! compare the throughput of handling the messages one by one and by the sharded workers,
+//! the handler sleeps to simulate the round trips of redis and chat rpc.
+//!
+//! run it with `cargo bench -p msg_gateway --bench shard`
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+use tokio::sync::Notify;
+
+use msg_gateway::shard::Sharded;
+
+const MESSAGES: usize = 2000;
+const SENDERS: usize = 200;
+const LATENCY: Duration = Duration::from_millis(2);
+
+async fn handle(_message: (String, usize)) {
+    tokio::time::sleep(LATENCY).await;
+}
+
+async fn sequential() -> Duration {
+    let (tx, mut rx) = mpsc::channel(1024);
+    let start = Instant::now();
+    tokio::spawn(async move {
+        for i in 0..MESSAGES {
+            tx.send((format!("user{}", i % SENDERS), i)).await.unwrap();
+        }
+    });
+    while let Some(message) = rx.recv().await {
+        handle(message).await;
+    }
+    start.elapsed()
+}
+
+async fn sharded(workers: usize) -> Duration {
+    let done = Arc::new(Notify::new());
+    let remaining = Arc::new(AtomicUsize::new(MESSAGES));
+    let cloned_done = done.clone();
+    let shards = Sharded::new(workers, 256, move |message| {
+        let done = cloned_done.clone();
+        let remaining = remaining.clone();
+        async move {
+            handle(message).await;
+            if remaining.fetch_sub(1, Ordering::Relaxed) == 1 {
+                done.notify_one();
+            }
+        }
+    });
+
+    let start = Instant::now();
+    for i in 0..MESSAGES {
+        let send_id = format!("user{}", i % SENDERS);
+        shards
+            .dispatch(&send_id, (send_id.clone(), i))
+            .await
+            .unwrap();
+    }
+    done.notified().await;
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    println!(
+        "{:<12} {:>8.1?} {:>10.0} msg/s",
+        name,
+        elapsed,
+        MESSAGES as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    println!(
+        "{} messages from {} senders, {:?} per message",
+        MESSAGES, SENDERS, LATENCY
+    );
+    report("sequential", sequential().await);
+    for workers in [4, 16, 64] {
+        report(&format!("{} workers", workers), sharded(workers).await);
+    }
+}
@@ -1,33 +1,118 @@
-use abi::message::PlatformType;
-use axum::extract::ws::{Message, WebSocket};
-use futures::stream::SplitSink;
-use futures::SinkExt;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use abi::message::{KnockOff, PlatformType};
+use axum::extract::ws::Message;
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
-use tokio::sync::RwLock;
 
-type ClientSender = Arc<RwLock<SplitSink<WebSocket, Message>>>;
+use crate::codec::Codec;
+use crate::sync::SyncGate;
+use crate::unacked::UnackedWindow;
+
+/// the error of putting message into the outbound queue
+#[derive(Debug)]
+pub enum SendError {
+    /// the queue is full, the message is dropped
+    Full,
+    /// the queue stays full longer than the threshold, the client should be disconnected
+    SlowConsumer,
+    /// the writer task is finished
+    Closed,
+}
 
 /// client
 #[derive(Debug)]
 pub struct Client {
-    // hold a ws connection sender
-    pub sender: ClientSender,
+    // the outbound queue, drained by the writer task of the connection
+    pub sender: Sender<Message>,
     // user id
     pub user_id: String,
-    // platform id
-    pub platform_id: String,
+    // device id, unique for each device of the user
+    pub device_id: String,
+    // connection id, distinguish the connections of the same device
+    pub conn_id: String,
+    // the category of the device
     pub platform: PlatformType,
-    pub notify_sender: Sender<()>,
+    // the wire format negotiated when connecting
+    pub codec: Codec,
+    // send the reason to close the connection
+    pub notify_sender: Sender<KnockOff>,
+    // monotonic milliseconds of the queue became full, 0 means not full
+    pub full_since: AtomicU64,
+    // how long the queue can stay full
+    pub slow_consumer_timeout: Duration,
+    // the messages waiting for ack, shared with the retry task of the connection
+    pub unacked: Arc<UnackedWindow>,
+    // milliseconds timestamp of the last frame received from the client
+    pub last_active: Arc<AtomicU64>,
+    // hold the live messages while the connection is syncing
+    pub sync_gate: Arc<SyncGate>,
 }
 
 #[allow(dead_code)]
 impl Client {
-    pub async fn send_text(&self, msg: String) -> Result<(), axum::Error> {
-        self.sender.write().await.send(Message::Text(msg)).await
+    pub fn send_text(&self, msg: String) -> Result<(), SendError> {
+        self.send(Message::Text(msg))
+    }
+
+    pub fn send_binary(&self, msg: Vec<u8>) -> Result<(), SendError> {
+        self.send(Message::Binary(msg))
     }
 
-    pub async fn send_binary(&self, msg: Vec<u8>) -> Result<(), axum::Error> {
-        self.sender.write().await.send(Message::Binary(msg)).await
+    /// send the live message with the receiver's seq,
+    /// it is held until the sync is completed if the connection is syncing
+    pub fn deliver(&self, seq: i64, frame: Message) -> Result<(), SendError> {
+        match self.sync_gate.hold(seq, frame) {
+            Ok(()) => Ok(()),
+            // the gate is full, the message will be sent again if it needs ack
+            Err(_) if self.sync_gate.is_syncing() => Err(SendError::Full),
+            Err(frame) => self.send(frame),
+        }
     }
+
+    /// put the message into the outbound queue without waiting,
+    /// a slow client should not block the others
+    pub fn send(&self, msg: Message) -> Result<(), SendError> {
+        match self.sender.try_send(msg) {
+            Ok(_) => {
+                self.full_since.store(0, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => {
+                let now = mono_millis();
+                let since = match self.full_since.compare_exchange(
+                    0,
+                    now,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => now,
+                    Err(since) => since,
+                };
+                // another thread may store a later time between reading the clock and here
+                if now.saturating_sub(since) > self.slow_consumer_timeout.as_millis() as u64 {
+                    Err(SendError::SlowConsumer)
+                } else {
+                    Err(SendError::Full)
+                }
+            }
+            Err(TrySendError::Closed(_)) => Err(SendError::Closed),
+        }
+    }
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// milliseconds elapsed since the first call, it is not affected by the adjustment of the wall clock,
+/// it starts from 1 to keep 0 for the unset state
+pub(crate) fn mono_millis() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_millis() as u64 + 1
 }
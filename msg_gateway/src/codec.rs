@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use axum::extract::ws::Message;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use abi::errors::Error;
+use abi::message::{MemberIds, Msg, MsgRead, MsgType, Presence, ReadCursor, ReadReceipt};
+
+/// the wire format of the frames, negotiated when the client connects
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// the generated protobuf schema, binary frames
+    Protobuf,
+    /// text frames
+    Json,
+    /// the layout of the rust structs, binary frames, kept for the legacy clients
+    #[default]
+    Bincode,
+}
+
+impl Codec {
+    /// encode the message as a frame
+    pub fn encode(&self, msg: &Msg) -> Result<Message, Error> {
+        match self {
+            Codec::Json => Ok(Message::Text(serde_json::to_string(msg)?)),
+            _ => Ok(Message::Binary(self.encode_content(msg)?)),
+        }
+    }
+
+    /// encode the value carried by the content of a message,
+    /// the contents built by the gateway for the client use the codec of the client
+    pub fn encode_content<T>(&self, value: &T) -> Result<Vec<u8>, Error>
+    where
+        T: prost::Message + Serialize,
+    {
+        match self {
+            Codec::Protobuf => Ok(value.encode_to_vec()),
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            Codec::Bincode => Ok(bincode::serialize(value)?),
+        }
+    }
+
+    /// the services behind the gateway encode the contents with bincode,
+    /// the contents of the types known by the gateway are re-encoded with the codec of the client,
+    /// the other contents are passed through
+    pub fn transcode<'a>(&self, msg: &'a Msg) -> Result<Cow<'a, Msg>, Error> {
+        if *self == Codec::Bincode {
+            return Ok(Cow::Borrowed(msg));
+        }
+        let content = match MsgType::try_from(msg.msg_type) {
+            Ok(MsgType::Presence) => self.transcode_content::<Presence>(&msg.content)?,
+            Ok(MsgType::ReadCursor) => self.transcode_content::<ReadCursor>(&msg.content)?,
+            Ok(MsgType::ReadReceipt | MsgType::GroupReadReceipt) => {
+                self.transcode_content::<ReadReceipt>(&msg.content)?
+            }
+            Ok(MsgType::GroupRemoveMember) => self.encode_content(&MemberIds {
+                member_ids: bincode::deserialize(&msg.content)?,
+            })?,
+            _ => return Ok(Cow::Borrowed(msg)),
+        };
+        let mut msg = msg.clone();
+        msg.content = content;
+        Ok(Cow::Owned(msg))
+    }
+
+    /// the reverse of `transcode`, the contents sent by the client which are read by the services
+    /// are decoded with the codec of the client and re-encoded with bincode
+    pub fn transcode_inbound(&self, msg: &mut Msg) -> Result<(), Error> {
+        if *self == Codec::Bincode {
+            return Ok(());
+        }
+        msg.content = match MsgType::try_from(msg.msg_type) {
+            Ok(MsgType::Read) => bincode::serialize(&self.decode::<MsgRead>(&msg.content)?)?,
+            Ok(MsgType::GroupRemoveMember) => {
+                bincode::serialize(&self.decode::<MemberIds>(&msg.content)?.member_ids)?
+            }
+            _ => return Ok(()),
+        };
+        Ok(())
+    }
+
+    fn transcode_content<T>(&self, content: &[u8]) -> Result<Vec<u8>, Error>
+    where
+        T: prost::Message + Serialize + DeserializeOwned,
+    {
+        self.encode_content(&bincode::deserialize::<T>(content)?)
+    }
+
+    /// decode a binary frame or the content of a message,
+    /// the text frames are always json
+    pub fn decode<T>(&self, data: &[u8]) -> Result<T, Error>
+    where
+        T: prost::Message + Default + DeserializeOwned,
+    {
+        match self {
+            Codec::Protobuf => T::decode(data).map_err(Error::internal),
+            Codec::Json => Ok(serde_json::from_slice(data)?),
+            Codec::Bincode => Ok(bincode::deserialize(data)?),
+        }
+    }
+}
+
+/// the frames of a message, encoded once for each codec in use
+pub struct Frames<'a> {
+    msg: &'a Msg,
+    frames: HashMap<Codec, Message>,
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(msg: &'a Msg) -> Self {
+        Self {
+            msg,
+            frames: HashMap::new(),
+        }
+    }
+
+    pub fn get(&mut self, codec: Codec) -> Option<Message> {
+        if let Some(frame) = self.frames.get(&codec) {
+            return Some(frame.clone());
+        }
+        match codec.transcode(self.msg).and_then(|msg| codec.encode(&msg)) {
+            Ok(frame) => {
+                self.frames.insert(codec, frame.clone());
+                Some(frame)
+            }
+            Err(e) => {
+                error!("encode message with {:?} error: {:?}", codec, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_round_trip() {
+        let msg = Msg {
+            send_id: "sender".to_string(),
+            receiver_id: "receiver".to_string(),
+            seq: 1,
+            content: vec![1, 2, 3],
+            ..Default::default()
+        };
+        for codec in [Codec::Protobuf, Codec::Json, Codec::Bincode] {
+            let decoded: Msg = match codec.encode(&msg).unwrap() {
+                Message::Text(text) => codec.decode(text.as_bytes()).unwrap(),
+                Message::Binary(data) => codec.decode(&data).unwrap(),
+                _ => unreachable!(),
+            };
+            assert_eq!(decoded, msg);
+        }
+    }
+
+    #[test]
+    fn test_transcode() {
+        let cursor = ReadCursor {
+            user_id: "user".to_string(),
+            conversation_id: "friend".to_string(),
+            seq: 3,
+            ..Default::default()
+        };
+        let msg = Msg {
+            msg_type: MsgType::ReadCursor as i32,
+            content: bincode::serialize(&cursor).unwrap(),
+            ..Default::default()
+        };
+        for codec in [Codec::Protobuf, Codec::Json, Codec::Bincode] {
+            let transcoded = codec.transcode(&msg).unwrap();
+            let decoded: ReadCursor = codec.decode(&transcoded.content).unwrap();
+            assert_eq!(decoded, cursor);
+        }
+
+        // the contents unknown by the gateway are passed through
+        let msg = Msg {
+            msg_type: MsgType::SingleMsg as i32,
+            content: vec![1, 2, 3],
+            ..Default::default()
+        };
+        let transcoded = Codec::Json.transcode(&msg).unwrap();
+        assert!(matches!(transcoded, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_transcode_inbound() {
+        let read = MsgRead {
+            msg_seq: vec![1, 2],
+            user_id: "user".to_string(),
+            conversation_id: "friend".to_string(),
+        };
+        let members = vec!["a".to_string(), "b".to_string()];
+        for codec in [Codec::Protobuf, Codec::Json] {
+            // the read frame sent by the client
+            let msg = Msg {
+                msg_type: MsgType::Read as i32,
+                content: codec.encode_content(&read).unwrap(),
+                ..Default::default()
+            };
+            let frame = codec.encode(&msg).unwrap();
+            let mut received: Msg = match frame {
+                Message::Text(text) => codec.decode(text.as_bytes()).unwrap(),
+                Message::Binary(data) => codec.decode(&data).unwrap(),
+                _ => unreachable!(),
+            };
+            codec.transcode_inbound(&mut received).unwrap();
+            let decoded: MsgRead = bincode::deserialize(&received.content).unwrap();
+            assert_eq!(decoded, read);
+
+            let mut msg = Msg {
+                msg_type: MsgType::GroupRemoveMember as i32,
+                content: codec
+                    .encode_content(&MemberIds {
+                        member_ids: members.clone(),
+                    })
+                    .unwrap(),
+                ..Default::default()
+            };
+            codec.transcode_inbound(&mut msg).unwrap();
+            let decoded: Vec<String> = bincode::deserialize(&msg.content).unwrap();
+            assert_eq!(decoded, members);
+            // and back to the client
+            let transcoded = codec.transcode(&msg).unwrap();
+            let decoded: MemberIds = codec.decode(&transcoded.content).unwrap();
+            assert_eq!(decoded.member_ids, members);
+        }
+
+        // the content which can not be decoded is rejected
+        let mut msg = Msg {
+            msg_type: MsgType::Read as i32,
+            content: b"not json".to_vec(),
+            ..Default::default()
+        };
+        assert!(Codec::Json.transcode_inbound(&mut msg).is_err());
+    }
+}
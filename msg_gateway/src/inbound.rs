@@ -0,0 +1,178 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use axum::extract::ws::{CloseFrame, Message};
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+
+use abi::message::{ContentType, Msg, MsgType, PlatformType, Presence, SyncSeq};
+
+use crate::client::now_millis;
+use crate::codec::Codec;
+use crate::manager::Manager;
+use crate::rate_limit::{Category, InboundLimiter};
+use crate::sync::SyncGate;
+use crate::unacked::UnackedWindow;
+use crate::ws_server::RATE_LIMITED_CODE;
+
+/// what the connection should do after handling a message
+pub enum Flow {
+    Continue,
+    /// the close frame is queued, wait for it to be written
+    Close,
+    /// the connection is broken
+    Break,
+}
+
+/// handle the messages sent by the client of a connection,
+/// shared by the websocket and the sse transports
+pub struct Inbound {
+    pub hub: Manager,
+    // the outbound queue of the connection
+    pub sender: mpsc::Sender<Message>,
+    pub user_id: String,
+    pub device_id: String,
+    pub platform: PlatformType,
+    pub codec: Codec,
+    pub unacked: Arc<UnackedWindow>,
+    pub sync_gate: Arc<SyncGate>,
+    pub limiter: InboundLimiter,
+}
+
+impl Inbound {
+    pub async fn handle(&mut self, mut msg: Msg) -> Flow {
+        // the client acknowledges a message, it is not sent to others
+        if msg.msg_type == MsgType::Ack as i32 {
+            if let Some(acked) = self.unacked.ack(msg.seq) {
+                self.hub.report_delivered(&self.user_id, acked);
+            }
+            return Flow::Continue;
+        }
+
+        // reply the application level heartbeat
+        if msg.msg_type == MsgType::Heartbeat as i32 {
+            let pong = Msg {
+                msg_type: MsgType::Heartbeat as i32,
+                send_time: now_millis() as i64,
+                ..Default::default()
+            };
+            match self.codec.encode(&pong) {
+                Ok(frame) => {
+                    if let Err(e) = self.sender.send(frame).await {
+                        error!("reply heartbeat error : {:?}", e);
+                        return Flow::Break;
+                    }
+                }
+                Err(e) => error!("heartbeat serialize error: {:?}", e),
+            }
+            return Flow::Continue;
+        }
+
+        let category = Category::of(MsgType::try_from(msg.msg_type).unwrap_or_default());
+        if !self.limiter.try_acquire(category).await {
+            debug!("{:?} message of {} is rate limited", category, self.user_id);
+            reject(&self.sender, self.codec, msg, "rate limited").await;
+            // the client keeps flooding, close the connection
+            if self.limiter.violate() {
+                warn!("disconnect {} for flooding", self.user_id);
+                let frame = CloseFrame {
+                    code: RATE_LIMITED_CODE,
+                    reason: Cow::Borrowed("rate limited"),
+                };
+                return match self.sender.send(Message::Close(Some(frame))).await {
+                    Ok(()) => Flow::Close,
+                    Err(_) => Flow::Break,
+                };
+            }
+            return Flow::Continue;
+        }
+
+        // stream the missing messages, the client keeps sending and acking meanwhile
+        if msg.msg_type == MsgType::Sync as i32 {
+            let from = match self.codec.decode::<SyncSeq>(&msg.content) {
+                Ok(from) => from,
+                Err(e) => {
+                    warn!("decode sync request of {} error: {:?}", self.user_id, e);
+                    reject(&self.sender, self.codec, msg, "invalid sync request").await;
+                    return Flow::Continue;
+                }
+            };
+            let hub = self.hub.clone();
+            let user_id = self.user_id.clone();
+            let sender = self.sender.clone();
+            let gate = self.sync_gate.clone();
+            let codec = self.codec;
+            tokio::spawn(async move {
+                if let Err(e) = hub.sync(&user_id, codec, &sender, &gate, from).await {
+                    error!("sync messages of {} error: {:?}", user_id, e);
+                    reject(&sender, codec, msg, "sync failed").await;
+                }
+            });
+            return Flow::Continue;
+        }
+
+        // the client can only send messages as the authenticated user
+        if msg.send_id != self.user_id {
+            warn!("send id {} mismatch user {}", msg.send_id, self.user_id);
+            reject(&self.sender, self.codec, msg, "send id mismatch").await;
+            return Flow::Continue;
+        }
+        // mark the sender device, the other devices of the sender will receive it
+        msg.device_id.clone_from(&self.device_id);
+        msg.platform = self.platform as i32;
+
+        // the signals are forwarded to the receivers directly, without seq and storage
+        if msg.msg_type == MsgType::Signal as i32 {
+            self.hub.send_signal(msg);
+            return Flow::Continue;
+        }
+
+        // the presence is set by the client, not stored as a message
+        if msg.msg_type == MsgType::Presence as i32 {
+            let result = match self.codec.decode::<Presence>(&msg.content) {
+                Ok(presence) => self.hub.change_presence(&self.user_id, presence).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("change presence of {} error: {:?}", self.user_id, e);
+                reject(&self.sender, self.codec, msg, "invalid presence").await;
+            }
+            return Flow::Continue;
+        }
+
+        // the services read the contents encoded with bincode
+        if let Err(e) = self.codec.transcode_inbound(&mut msg) {
+            warn!("decode content of {} error: {:?}", self.user_id, e);
+            reject(&self.sender, self.codec, msg, "invalid content").await;
+            return Flow::Continue;
+        }
+
+        // todo need to judge the local id is empty by message type
+        // if msg.local_id.is_empty() {
+        //     warn!("receive empty message");
+        //     continue;
+        // }
+        if self.hub.broadcast(msg).await.is_err() {
+            // if broadcast not available, close the connection
+            return Flow::Break;
+        }
+        Flow::Continue
+    }
+}
+
+/// reply an error to the client instead of sending the message
+pub async fn reject(sender: &mpsc::Sender<Message>, codec: Codec, mut msg: Msg, reason: &str) {
+    msg.msg_type = MsgType::MsgRecResp as i32;
+    msg.content_type = ContentType::Error as i32;
+    msg.content = reason.as_bytes().to_vec();
+    let frame = match codec.encode(&msg) {
+        Ok(frame) => frame,
+        Err(e) => {
+            error!("msg serialize error: {:?}", e);
+            return;
+        }
+    };
+    if let Err(e) = sender.send(frame).await {
+        error!("send reject reason to client error: {}", e);
+    }
+}
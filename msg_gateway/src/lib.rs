@@ -1,4 +1,12 @@
 mod client;
+mod codec;
+mod inbound;
 mod manager;
+mod metrics;
+mod rate_limit;
 pub mod rpc;
+pub mod shard;
+mod sse;
+mod sync;
+mod unacked;
 pub mod ws_server;
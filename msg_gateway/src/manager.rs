@@ -1,47 +1,215 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 
-use abi::config::Config;
+use abi::config::{Config, LoginPolicy};
 use dashmap::DashMap;
 use tokio::sync::mpsc;
+use tonic::transport::Channel;
 use tracing::{debug, error, info, warn};
 
-use crate::client::Client;
+use crate::client::{now_millis, Client, SendError};
+use crate::codec::{Codec, Frames};
+use crate::metrics::Metrics;
+use crate::shard::Sharded;
+use crate::sync::SyncGate;
 use abi::errors::Error;
 use abi::message::chat_service_client::ChatServiceClient;
+use abi::message::msg_service_client::MsgServiceClient;
 use abi::message::{
-    ContentType, GroupMemSeq, Msg, MsgResponse, MsgType, PlatformType, SendMsgRequest,
+    Connection, ContentType, GatewayStats, GroupMemSeq, KnockOff, KnockOffReason, KnockOffRequest,
+    Msg, MsgResponse, MsgType, PlatformType, Presence, PresenceStatus, SendMsgRequest,
+    SendSignalRequest, SyncSeq,
 };
+use axum::extract::ws::Message;
 use cache::Cache;
+use db::message::MsgRecBoxRepo;
 use utils::service_discovery::LbWithServiceDiscovery;
 
+/// the capacity of each worker's queue
+const WORKER_QUEUE_SIZE: usize = 256;
+
 type UserID = String;
+type DeviceID = String;
 /// client hub
-type Hub = Arc<DashMap<UserID, DashMap<PlatformType, Client>>>;
+type Hub = Arc<DashMap<UserID, DashMap<DeviceID, Client>>>;
 
 /// manage the client
 #[derive(Clone)]
 pub struct Manager {
-    tx: mpsc::Sender<Msg>,
+    /// the workers handling the inbound messages, started by `run`
+    shards: Arc<OnceLock<Sharded<Msg>>>,
     pub hub: Hub,
     pub cache: Arc<dyn Cache>,
+    /// the inbox, the missing messages are synced from it
+    msg_box: Arc<dyn MsgRecBoxRepo>,
     pub chat_rpc: ChatServiceClient<LbWithServiceDiscovery>,
+    /// the address of current gateway's rpc service,
+    /// it is used to tell the pusher which gateway holds the user's connection
+    gateway_id: String,
+    /// which sessions should be knocked off when a new session is registered
+    login_policy: LoginPolicy,
+    /// rpc protocol of the gateways
+    gateway_protocol: String,
+    /// rpc clients of other gateways, used to knock off the sessions held by them
+    gateways: Arc<DashMap<String, MsgServiceClient<Channel>>>,
+    pub metrics: Arc<Metrics>,
+    /// the gateway is shutting down, no more connections are accepted
+    draining: Arc<AtomicBool>,
+    /// how many workers handle the inbound messages concurrently
+    workers: usize,
+    /// carried by the knock off requests to other gateways
+    admin_token: Option<String>,
 }
 
 #[allow(dead_code)]
 impl Manager {
-    pub async fn new(tx: mpsc::Sender<Msg>, config: &Config) -> Self {
+    pub async fn new(config: &Config) -> Self {
         let cache = cache::cache(config);
         let chat_rpc = utils::get_rpc_client(config, config.rpc.chat.name.clone())
             .await
             .expect("chat rpc can't open");
+        let msg_box = db::msg_rec_box_repo(config)
+            .await
+            .expect("message box can't open");
         Manager {
-            tx,
+            shards: Arc::new(OnceLock::new()),
             hub: Arc::new(DashMap::new()),
             cache,
+            msg_box,
             chat_rpc,
+            gateway_id: config.rpc.ws.rpc_server_url(),
+            login_policy: config.websocket.login_policy,
+            gateway_protocol: config.rpc.ws.protocol.clone(),
+            gateways: Arc::new(DashMap::new()),
+            metrics: Arc::new(Metrics::default()),
+            draining: Arc::new(AtomicBool::new(false)),
+            workers: config.websocket.workers,
+            admin_token: config.websocket.admin_token.clone(),
         }
     }
 
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    /// stop accepting new connections
+    pub fn start_drain(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// close all connections gradually in the window,
+    /// each client is told to reconnect to one of the alternative gateways,
+    /// so that they do not rush to the load balancer at the same time
+    pub async fn drain(&self, alternatives: &[String], window: Duration) {
+        let clients = self.clients();
+        info!("drain {} connections in {:?}", clients.len(), window);
+        let interval = window / clients.len().max(1) as u32;
+        for (i, (user_id, device_id)) in clients.into_iter().enumerate() {
+            let gateway = if alternatives.is_empty() {
+                String::new()
+            } else {
+                alternatives[i % alternatives.len()].clone()
+            };
+            let knock_off = KnockOff {
+                reason: KnockOffReason::Drain as i32,
+                gateway,
+                ..Default::default()
+            };
+            self.knock_off(&user_id, &device_id, knock_off).await;
+            tokio::time::sleep(interval).await;
+        }
+
+        // the connections registered while taking the snapshot
+        for (user_id, device_id) in self.clients() {
+            let knock_off = KnockOff {
+                reason: KnockOffReason::Drain as i32,
+                gateway: alternatives.first().cloned().unwrap_or_default(),
+                ..Default::default()
+            };
+            self.knock_off(&user_id, &device_id, knock_off).await;
+        }
+    }
+
+    /// the connection counts of this gateway
+    pub fn stats(&self) -> GatewayStats {
+        let mut stats = GatewayStats {
+            gateway: self.gateway_id.clone(),
+            users: self.hub.len() as u64,
+            slow_consumer_evicted: self.metrics.slow_consumer_evicted.load(Ordering::Relaxed),
+            dropped_messages: self.metrics.dropped_messages.load(Ordering::Relaxed),
+            draining: self.is_draining(),
+            ..Default::default()
+        };
+        for entry in self.hub.iter() {
+            for client in entry.value().iter() {
+                stats.connections += 1;
+                *stats
+                    .platforms
+                    .entry(client.platform.as_str_name().to_string())
+                    .or_default() += 1;
+            }
+        }
+        stats
+    }
+
+    /// the connections of the user held by this gateway
+    pub fn user_connections(&self, user_id: &str) -> Vec<Connection> {
+        self.hub
+            .get(user_id)
+            .map(|clients| {
+                clients
+                    .iter()
+                    .map(|client| Connection {
+                        user_id: user_id.to_string(),
+                        device_id: client.device_id.clone(),
+                        platform: client.platform as i32,
+                        conn_id: client.conn_id.clone(),
+                        gateway: self.gateway_id.clone(),
+                        last_active: client.last_active.load(Ordering::Relaxed) as i64,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// close the connection of the device, or all devices of the user if device id is empty
+    pub async fn disconnect(&self, user_id: &str, device_id: &str) {
+        let devices: Vec<DeviceID> = if device_id.is_empty() {
+            self.hub
+                .get(user_id)
+                .map(|clients| clients.iter().map(|c| c.key().clone()).collect())
+                .unwrap_or_default()
+        } else {
+            vec![device_id.to_string()]
+        };
+        for device_id in devices {
+            let knock_off = KnockOff {
+                reason: KnockOffReason::Admin as i32,
+                device_id: device_id.clone(),
+                ..Default::default()
+            };
+            info!("disconnect user {} device {}", user_id, device_id);
+            self.knock_off(user_id, &device_id, knock_off).await;
+        }
+    }
+
+    /// snapshot of all connections, (user id, device id)
+    fn clients(&self) -> Vec<(UserID, DeviceID)> {
+        self.hub
+            .iter()
+            .flat_map(|entry| {
+                let user_id = entry.key().clone();
+                entry
+                    .value()
+                    .iter()
+                    .map(|client| (user_id.clone(), client.key().clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
     pub async fn send_group(&self, obj_ids: Vec<GroupMemSeq>, mut msg: Msg) {
         self.send_to_self(&msg.send_id, &msg).await;
 
@@ -49,124 +217,515 @@ impl Manager {
         msg.send_seq = 0;
 
         for mem in obj_ids {
-            if let Some(clients) = self.hub.get(&mem.mem_id) {
-                // Modify only the seq in the message and serialize it.
-                msg.seq = mem.cur_seq;
+            // Modify only the seq in the message and serialize it.
+            msg.seq = mem.cur_seq;
 
-                // Send message to all clients
-                self.send_msg_to_clients(&clients, &msg).await;
-            }
+            // Send message to all clients
+            self.send_msg_to_clients(&mem.mem_id, &msg).await;
         }
     }
 
+    /// send to the sender's other devices
     async fn send_to_self(&self, id: &str, msg: &Msg) {
-        if let Some(client) = self.hub.get(id) {
-            // send to self which another platform client
-            let platform = if msg.platform == PlatformType::Mobile as i32 {
-                PlatformType::Desktop
-            } else {
-                PlatformType::Mobile
-            };
-            if let Some(sender) = client.get(&platform) {
-                let content = match bincode::serialize(msg) {
-                    Ok(res) => res,
-                    Err(_) => {
-                        error!("msg serialize error");
-                        return;
-                    }
-                };
-                if let Err(e) = sender.send_binary(content).await {
-                    error!("send to self error: {}", e)
-                }
-            }
-        }
+        self.send_to_devices(id, msg, Some(&msg.device_id), false)
+            .await;
     }
 
     pub async fn send_single_msg(&self, obj_id: &str, msg: &Msg) {
-        if let Some(clients) = self.hub.get(obj_id) {
-            self.send_msg_to_clients(&clients, msg).await;
+        // the message to the user self, like the read cursor, goes to the other devices only
+        if obj_id != msg.send_id {
+            self.send_msg_to_clients(obj_id, msg).await;
         }
         self.send_to_self(&msg.send_id, msg).await;
     }
 
-    async fn send_msg_to_clients(&self, clients: &DashMap<PlatformType, Client>, msg: &Msg) {
-        match clients.len() {
-            0 => error!("no client found"),
-            1 => {
-                let content = match bincode::serialize(msg) {
-                    Ok(res) => res,
-                    Err(e) => {
-                        error!("msg serialize error: {}", e);
-                        return;
-                    }
-                };
-                if let Some(client) = clients.iter().next() {
-                    if let Err(e) = client.value().send_binary(content).await {
-                        error!("send message error: {}", e);
-                    }
+    /// send message to all devices of the user,
+    /// the message with seq should be acked by the client, otherwise it will be sent again
+    async fn send_msg_to_clients(&self, user_id: &str, msg: &Msg) {
+        self.send_to_devices(user_id, msg, None, Self::need_ack(msg))
+            .await;
+    }
+
+    /// the control messages are not in the receiver's sequence, they are never acked
+    fn need_ack(msg: &Msg) -> bool {
+        msg.seq > 0
+            && !matches!(
+                MsgType::try_from(msg.msg_type),
+                Ok(MsgType::MsgRecResp
+                    | MsgType::Ack
+                    | MsgType::Delivered
+                    | MsgType::Signal
+                    | MsgType::Presence
+                    | MsgType::Heartbeat
+                    | MsgType::SyncComplete)
+            )
+    }
+
+    /// put the message into the outbound queues of the user's devices,
+    /// the message is encoded once for each codec used by the devices,
+    /// the devices which can not keep up with the messages will be disconnected
+    async fn send_to_devices(
+        &self,
+        user_id: &str,
+        msg: &Msg,
+        exclude: Option<&str>,
+        need_ack: bool,
+    ) {
+        let mut slow_devices = Vec::new();
+        let mut frames = Frames::new(msg);
+        if let Some(clients) = self.hub.get(user_id) {
+            for client in clients.iter() {
+                if exclude == Some(client.key().as_str()) {
+                    continue;
                 }
-            }
-            2 => {
-                let content = match bincode::serialize(msg) {
-                    Ok(res) => res,
-                    Err(e) => {
-                        error!("msg serialize error: {}", e);
-                        return;
-                    }
+                let Some(frame) = frames.get(client.codec) else {
+                    continue;
                 };
-                let mut iter = clients.iter();
-                if let Some(first_client) = iter.next() {
-                    if let Err(e) = first_client.value().send_binary(content.clone()).await {
-                        error!("send message error: {}", e);
-                    }
+                // the seq is the receiver's, the copies to the sender's devices are not covered by it
+                let seq = if msg.send_id == user_id { 0 } else { msg.seq };
+                let result = client.value().deliver(seq, frame.clone());
+                // the message dropped by the full queue will be sent again too
+                if need_ack && matches!(result, Ok(_) | Err(SendError::Full)) {
+                    client.unacked.push(msg, frame);
                 }
-                if let Some(second_client) = iter.next() {
-                    if let Err(e) = second_client.value().send_binary(content).await {
-                        error!("send message error: {}", e);
+                match result {
+                    Ok(_) => {}
+                    Err(SendError::SlowConsumer) => slow_devices.push(client.key().clone()),
+                    Err(e) => {
+                        self.metrics
+                            .dropped_messages
+                            .fetch_add(1, Ordering::Relaxed);
+                        error!("send message to {} error: {:?}", client.key(), e);
                     }
                 }
             }
-            _ => warn!("Unexpected number of clients: {}", clients.len()),
+        }
+
+        for device_id in slow_devices {
+            self.evict(user_id, &device_id).await;
+        }
+    }
+
+    /// remove the connection records left by the last run of this gateway,
+    /// the gateway may be crashed or killed without unregistering them
+    pub async fn clear_registry(&self) {
+        if let Err(e) = self.cache.clear_gateway(&self.gateway_id).await {
+            error!(
+                "clear the registry of gateway {} error: {:?}",
+                self.gateway_id, e
+            );
+        }
+    }
+
+    /// refresh the lease of the gateway in the registry until the gateway stops,
+    /// the pusher ignores the connections of this gateway once the lease is expired
+    pub async fn keep_lease(&self, ttl: u64) {
+        let mut interval = tokio::time::interval(Duration::from_secs((ttl / 3).max(1)));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self
+                .cache
+                .refresh_gateway_lease(&self.gateway_id, ttl)
+                .await
+            {
+                error!(
+                    "refresh the lease of gateway {} error: {:?}",
+                    self.gateway_id, e
+                );
+            }
+        }
+    }
+
+    /// disconnect the client whose outbound queue stays full
+    async fn evict(&self, user_id: &str, device_id: &str) {
+        // drop the client, the connection will be closed by the notify signal
+        if self.remove_client(user_id, device_id).await.is_some() {
+            self.metrics
+                .slow_consumer_evicted
+                .fetch_add(1, Ordering::Relaxed);
+            warn!(
+                "evict slow client, user: {}, device: {}",
+                user_id, device_id
+            );
         }
     }
 
     // register client
     pub async fn register(&mut self, id: String, client: Client) {
-        self.hub
-            .entry(id)
+        let device_id = client.device_id.clone();
+        let platform = client.platform;
+
+        // the sessions before this one, queried before the registry is overwritten
+        let devices = self.query_devices(&id).await;
+        let was_offline = devices.is_empty();
+
+        // record the connection in the registry, so that the pusher can find this gateway
+        if let Err(e) = self
+            .cache
+            .register_gateway(&id, &device_id, platform as i32, &self.gateway_id)
+            .await
+        {
+            error!("register gateway for user {} error: {:?}", id, e);
+        }
+
+        // close the sessions which are not allowed by the login policy
+        self.knock_off_displaced(&id, &device_id, platform, devices)
+            .await;
+
+        let old = self
+            .hub
+            .entry(id.clone())
             .or_default()
-            .insert(client.platform, client);
+            .insert(device_id.clone(), client);
+
+        // the same device connected again, close the old connection
+        if let Some(old) = old {
+            let knock_off = KnockOff {
+                reason: KnockOffReason::SameDevice as i32,
+                device_id: device_id.clone(),
+                platform: platform as i32,
+                ..Default::default()
+            };
+            if let Err(e) = old.notify_sender.send(knock_off).await {
+                error!("notify old connection of user {} error: {}", id, e);
+            }
+        }
+
+        self.heartbeat(&id).await;
+        if was_offline {
+            self.publish_presence(&id);
+        }
     }
 
-    pub async fn unregister(&mut self, id: String, platform: PlatformType) {
-        let mut flag = false;
-        if let Some(clients) = self.hub.get_mut(&id) {
-            if clients.len() == 1 {
-                flag = true;
+    /// the sessions of the user held by all gateways: (device_id, platform, gateway)
+    async fn query_devices(&self, user_id: &str) -> Vec<(String, i32, String)> {
+        match self.cache.query_user_devices(user_id).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("query devices of user {} error: {:?}", user_id, e);
+                // only the local sessions can be found
+                self.hub
+                    .get(user_id)
+                    .map(|clients| {
+                        clients
+                            .iter()
+                            .map(|c| {
+                                (
+                                    c.device_id.clone(),
+                                    c.platform as i32,
+                                    self.gateway_id.clone(),
+                                )
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            }
+        }
+    }
+
+    /// knock off the user's sessions which are displaced by the new session according to the login policy,
+    /// the sessions may be held by other gateways
+    async fn knock_off_displaced(
+        &self,
+        user_id: &str,
+        device_id: &str,
+        platform: PlatformType,
+        devices: Vec<(String, i32, String)>,
+    ) {
+        for (other_device, other_platform, gateway) in devices {
+            let reason = if other_device == device_id {
+                // the old connection of the same device on this gateway is replaced in the hub
+                if gateway == self.gateway_id {
+                    continue;
+                }
+                KnockOffReason::SameDevice
             } else {
-                clients.remove(&platform);
+                match self.login_policy {
+                    LoginPolicy::Unlimited => continue,
+                    LoginPolicy::SinglePlatform if other_platform != platform as i32 => continue,
+                    LoginPolicy::SinglePlatform => KnockOffReason::SamePlatform,
+                    LoginPolicy::Single => KnockOffReason::OtherDevice,
+                }
+            };
+
+            let knock_off = KnockOff {
+                reason: reason as i32,
+                device_id: device_id.to_string(),
+                platform: platform as i32,
+                ..Default::default()
+            };
+            debug!(
+                "knock off user {} device {} on {}: {:?}",
+                user_id, other_device, gateway, reason
+            );
+
+            if gateway == self.gateway_id {
+                self.knock_off(user_id, &other_device, knock_off).await;
+                continue;
+            }
+
+            let request = KnockOffRequest {
+                user_id: user_id.to_string(),
+                device_id: other_device,
+                knock_off: Some(knock_off),
+            };
+            if let Err(e) = self.knock_off_remote(&gateway, request).await {
+                error!(
+                    "knock off user {} on gateway {} error: {:?}",
+                    user_id, gateway, e
+                );
             }
+        }
+    }
+
+    async fn knock_off_remote(&self, gateway: &str, request: KnockOffRequest) -> Result<(), Error> {
+        let mut client = self.gateway_client(gateway).await?;
+        client
+            .knock_off(utils::admin_request(request, self.admin_token.as_deref()))
+            .await?;
+        Ok(())
+    }
+
+    /// the rpc client of other gateway
+    async fn gateway_client(&self, gateway: &str) -> Result<MsgServiceClient<Channel>, Error> {
+        if let Some(client) = self.gateways.get(gateway) {
+            return Ok(client.clone());
+        }
+        let client =
+            MsgServiceClient::connect(format!("{}://{}", self.gateway_protocol, gateway)).await?;
+        self.gateways.insert(gateway.to_string(), client.clone());
+        Ok(client)
+    }
+
+    /// forward the signal to the gateways which hold the receivers,
+    /// it does not go through the chat service, so it is never sequenced or stored
+    pub fn send_signal(&self, msg: Msg) {
+        let manager = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = manager.forward_signal(msg).await {
+                error!("send signal error: {:?}", e);
+            }
+        });
+    }
+
+    async fn forward_signal(&self, msg: Msg) -> Result<(), Error> {
+        let receivers = if msg.group_id.is_empty() {
+            vec![msg.receiver_id.clone()]
+        } else {
+            let mut members = self.cache.query_group_members_id(&msg.group_id).await?;
+            if !members.contains(&msg.send_id) {
+                return Err(Error::bad_request("sender is not a member of the group"));
+            }
+            members.retain(|id| id != &msg.send_id);
+            members
+        };
+
+        // group the receivers by gateway
+        let mut gateways: HashMap<String, Vec<String>> = HashMap::new();
+        for (user_id, list) in self.cache.query_user_gateways(&receivers).await? {
+            for gateway in list {
+                gateways.entry(gateway).or_default().push(user_id.clone());
+            }
+        }
+
+        for (gateway, receivers) in gateways {
+            if gateway == self.gateway_id {
+                self.send_signal_to_users(&receivers, &msg);
+                continue;
+            }
+            let request = SendSignalRequest {
+                message: Some(msg.clone()),
+                receivers,
+            };
+            let result = match self.gateway_client(&gateway).await {
+                Ok(mut client) => client.send_signal(request).await.map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("send signal to gateway {} error: {:?}", gateway, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// send the signal to the local connections of the receivers,
+    /// the signal is dropped if the queue is full
+    pub fn send_signal_to_users(&self, receivers: &[String], msg: &Msg) {
+        let mut frames = Frames::new(msg);
+        for user_id in receivers {
+            if let Some(clients) = self.hub.get(user_id) {
+                for client in clients.iter() {
+                    let Some(frame) = frames.get(client.codec) else {
+                        continue;
+                    };
+                    if let Err(e) = client.send(frame) {
+                        debug!("send signal to {} error: {:?}", client.key(), e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// close the connection of the device, the client will receive the reason
+    pub async fn knock_off(&self, user_id: &str, device_id: &str, knock_off: KnockOff) {
+        let Some(client) = self.remove_client(user_id, device_id).await else {
+            return;
         };
-        if flag {
-            self.hub.remove(&id);
+
+        if let Err(e) = client.notify_sender.send(knock_off).await {
+            error!("notify connection of user {} error: {}", user_id, e);
+        }
+    }
+
+    /// remove the client from hub and registry,
+    /// the connection will not unregister itself after removed
+    async fn remove_client(&self, user_id: &str, device_id: &str) -> Option<Client> {
+        let client = self
+            .hub
+            .get(user_id)
+            .and_then(|clients| clients.remove(device_id))
+            .map(|(_, client)| client)?;
+        self.hub.remove_if(user_id, |_, clients| clients.is_empty());
+
+        if let Err(e) = self
+            .cache
+            .unregister_gateway(user_id, device_id, &self.gateway_id)
+            .await
+        {
+            error!("unregister gateway for user {} error: {:?}", user_id, e);
+        }
+        self.leave(user_id).await;
+        Some(client)
+    }
+
+    pub async fn unregister(&mut self, id: String, device_id: String, conn_id: String) {
+        // the device may have connected again, do not remove the new connection
+        let removed = self
+            .hub
+            .get(&id)
+            .and_then(|clients| clients.remove_if(&device_id, |_, c| c.conn_id == conn_id))
+            .is_some();
+        // remove the user if there is no device connected
+        self.hub.remove_if(&id, |_, clients| clients.is_empty());
+        if !removed {
+            return;
+        }
+
+        if let Err(e) = self
+            .cache
+            .unregister_gateway(&id, &device_id, &self.gateway_id)
+            .await
+        {
+            error!("unregister gateway for user {} error: {:?}", id, e);
+        }
+        self.leave(&id).await;
+        debug!("unregister client: {:?}, device: {}", id, device_id);
+    }
+
+    /// record the last seen time of the user, and publish the presence if all devices are gone
+    async fn leave(&self, user_id: &str) {
+        self.heartbeat(user_id).await;
+        match self.cache.query_presence(&[user_id.to_string()]).await {
+            Ok(presences) => {
+                if presences
+                    .first()
+                    .is_some_and(|p| p.status == PresenceStatus::Offline as i32)
+                {
+                    self.publish_presence(user_id);
+                }
+            }
+            Err(e) => error!("query presence of user {} error: {:?}", user_id, e),
+        }
+    }
+
+    /// refresh the last seen time of the user
+    pub async fn heartbeat(&self, user_id: &str) {
+        if let Err(e) = self
+            .cache
+            .touch_presence(user_id, now_millis() as i64)
+            .await
+        {
+            error!("touch presence of user {} error: {:?}", user_id, e);
+        }
+    }
+
+    /// the user changes the presence status manually, offline is not allowed,
+    /// the user is offline only if all devices are disconnected
+    pub async fn change_presence(&self, user_id: &str, presence: Presence) -> Result<(), Error> {
+        let status = PresenceStatus::try_from(presence.status)
+            .map_err(|_| Error::bad_request("invalid presence status"))?;
+        if status == PresenceStatus::Offline {
+            return Err(Error::bad_request("invalid presence status"));
         }
-        debug!("unregister client: {:?}", id);
+        self.cache.set_presence(user_id, status).await?;
+        self.publish_presence(user_id);
+        Ok(())
     }
 
-    pub async fn run(&mut self, mut receiver: mpsc::Receiver<Msg>) {
-        info!("manager start");
+    /// send the presence of the user to the friends through the message pipeline
+    pub fn publish_presence(&self, user_id: &str) {
+        let manager = self.clone();
+        let user_id = user_id.to_string();
+        tokio::spawn(async move {
+            let presence = match manager
+                .cache
+                .query_presence(std::slice::from_ref(&user_id))
+                .await
+            {
+                Ok(mut presences) if !presences.is_empty() => presences.remove(0),
+                Ok(_) => return,
+                Err(e) => {
+                    error!("query presence of user {} error: {:?}", user_id, e);
+                    return;
+                }
+            };
+            let content = match bincode::serialize(&presence) {
+                Ok(content) => content,
+                Err(e) => {
+                    error!("serialize presence error: {:?}", e);
+                    return;
+                }
+            };
+            let msg = Msg {
+                send_id: user_id,
+                msg_type: MsgType::Presence as i32,
+                content,
+                ..Default::default()
+            };
+            match manager.send_rpc_message(msg).await {
+                Ok(response) if !response.err.is_empty() => {
+                    error!("publish presence error: {:?}", response.err)
+                }
+                Ok(_) => {}
+                Err(e) => error!("publish presence error: {:?}", e),
+            }
+        });
+    }
 
-        // read the message from the channel
-        while let Some(mut message) = receiver.recv().await {
-            self.process_message(&mut message).await;
+    /// start the workers, the messages are handled by the workers sharded by the sender,
+    /// so the messages of the same sender keep the order
+    pub fn run(&self) {
+        info!("manager start with {} workers", self.workers);
 
-            // reply send result
-            debug!("reply message:{:?}", message);
-            self.send_single_msg(&message.send_id, &message).await;
+        let manager = self.clone();
+        let shards = Sharded::new(self.workers, WORKER_QUEUE_SIZE, move |message| {
+            let manager = manager.clone();
+            async move { manager.handle_message(message).await }
+        });
+        if self.shards.set(shards).is_err() {
+            error!("manager workers are already started");
         }
     }
 
-    async fn process_message(&mut self, message: &mut Msg) {
+    async fn handle_message(&self, mut message: Msg) {
+        self.process_message(&mut message).await;
+
+        // reply send result
+        debug!("reply message:{:?}", message);
+        self.send_single_msg(&message.send_id, &message).await;
+    }
+
+    async fn process_message(&self, message: &mut Msg) {
         // increment the send sequence in cache
         //  we do not operate the database here about saving send sequence
         // we do that in the consumer module
@@ -201,6 +760,137 @@ impl Manager {
         }
     }
 
+    /// send the messages after the client's seqs in order, then the sync complete marker,
+    /// the live messages are held by the gate until the marker is sent.
+    /// the sender waits if the outbound queue is full, it slows down reading the inbox
+    pub async fn sync(
+        &self,
+        user_id: &str,
+        codec: Codec,
+        sender: &mpsc::Sender<Message>,
+        gate: &SyncGate,
+        from: SyncSeq,
+    ) -> Result<(), Error> {
+        if !gate.close() {
+            return Err(Error::bad_request("sync is in progress"));
+        }
+        let result = self.stream_missing(user_id, codec, sender, from).await;
+
+        let synced = result.as_ref().map(|seq| seq.seq).unwrap_or_default();
+        for frame in gate.open(synced) {
+            if sender.send(frame).await.is_err() {
+                break;
+            }
+        }
+        result.map(|_| ())
+    }
+
+    async fn stream_missing(
+        &self,
+        user_id: &str,
+        codec: Codec,
+        sender: &mpsc::Sender<Message>,
+        from: SyncSeq,
+    ) -> Result<SyncSeq, Error> {
+        let (seq, send_seq) = self.cache.get_cur_seq(user_id).await?;
+        // the cursors updated during the sync are pushed as live messages
+        let to = SyncSeq {
+            seq,
+            send_seq,
+            cursor_time: now_millis() as i64,
+        };
+        debug!("sync {} from {:?} to {:?}", user_id, from, to);
+
+        let received = if from.seq < to.seq {
+            Some(
+                self.msg_box
+                    .get_messages_stream(user_id, from.seq + 1, to.seq)
+                    .await?,
+            )
+        } else {
+            None
+        };
+        let sent = if from.send_seq < to.send_seq {
+            Some(
+                self.msg_box
+                    .get_sent_messages_stream(user_id, from.send_seq + 1, to.send_seq)
+                    .await?,
+            )
+        } else {
+            None
+        };
+
+        for mut rx in [received, sent].into_iter().flatten() {
+            while let Some(msg) = rx.recv().await {
+                let msg = msg?;
+                let frame = codec.encode(codec.transcode(&msg)?.as_ref())?;
+                sender
+                    .send(frame)
+                    .await
+                    .map_err(|e| Error::broadcast(Box::new(e)))?;
+            }
+        }
+
+        // the read cursors of the conversations, the unread counts are updated by them
+        for cursor in self
+            .msg_box
+            .get_read_cursors(user_id, from.cursor_time)
+            .await?
+        {
+            let msg = Msg {
+                send_id: user_id.to_string(),
+                receiver_id: user_id.to_string(),
+                msg_type: MsgType::ReadCursor as i32,
+                content: codec.encode_content(&cursor)?,
+                ..Default::default()
+            };
+            sender
+                .send(codec.encode(&msg)?)
+                .await
+                .map_err(|e| Error::broadcast(Box::new(e)))?;
+        }
+
+        let complete = Msg {
+            msg_type: MsgType::SyncComplete as i32,
+            content: codec.encode_content(&to)?,
+            ..Default::default()
+        };
+        sender
+            .send(codec.encode(&complete)?)
+            .await
+            .map_err(|e| Error::broadcast(Box::new(e)))?;
+        Ok(to)
+    }
+
+    /// tell the sender that the message is delivered to the receiver,
+    /// the report goes through the chat service like other messages.
+    /// only single messages are reported, the group messages would flood the sender
+    pub fn report_delivered(&self, user_id: &str, msg: Msg) {
+        if !msg.group_id.is_empty() || msg.send_id == user_id {
+            return;
+        }
+        let delivered = Msg {
+            send_id: user_id.to_string(),
+            receiver_id: msg.send_id,
+            server_id: msg.server_id,
+            local_id: msg.local_id,
+            // the seq is the receiver's, the sender finds the message by the server id
+            seq: 0,
+            msg_type: MsgType::Delivered as i32,
+            ..Default::default()
+        };
+        let manager = self.clone();
+        tokio::spawn(async move {
+            match manager.send_rpc_message(delivered).await {
+                Ok(response) if !response.err.is_empty() => {
+                    error!("report delivered error: {:?}", response.err)
+                }
+                Ok(_) => {}
+                Err(e) => error!("report delivered error: {:?}", e),
+            }
+        });
+    }
+
     async fn send_rpc_message(&self, message: Msg) -> Result<MsgResponse, tonic::Status> {
         let mut chat_rpc = self.chat_rpc.clone();
         chat_rpc
@@ -217,10 +907,41 @@ impl Manager {
         message.content = error.to_string().into_bytes();
     }
 
+    /// put the message into the queue of its worker,
+    /// it waits if the queue is full, which only holds back the senders of the same worker
     pub async fn broadcast(&self, msg: Msg) -> Result<(), Error> {
-        self.tx
-            .send(msg)
+        let Some(shards) = self.shards.get() else {
+            return Err(Error::internal_with_details(
+                "manager workers are not started",
+            ));
+        };
+        let send_id = msg.send_id.clone();
+        shards
+            .dispatch(&send_id, msg)
             .await
             .map_err(|e| Error::broadcast(Box::new(e)))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_need_ack() {
+        let mut msg = Msg {
+            seq: 3,
+            msg_type: MsgType::SingleMsg as i32,
+            ..Default::default()
+        };
+        assert!(Manager::need_ack(&msg));
+
+        // the delivery report must not take a slot of the sender's window
+        msg.msg_type = MsgType::Delivered as i32;
+        assert!(!Manager::need_ack(&msg));
+
+        msg.msg_type = MsgType::SingleMsg as i32;
+        msg.seq = 0;
+        assert!(!Manager::need_ack(&msg));
+    }
+}
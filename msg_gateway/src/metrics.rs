@@ -0,0 +1,10 @@
+use std::sync::atomic::AtomicU64;
+
+/// the counters of the gateway
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// the connections disconnected because their outbound queue stays full
+    pub slow_consumer_evicted: AtomicU64,
+    /// the messages dropped because the outbound queue is full
+    pub dropped_messages: AtomicU64,
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use abi::config::{RateLimit, RateLimitConfig};
+use abi::message::MsgType;
+use cache::Cache;
+use tracing::warn;
+
+/// token bucket, refill `rate` tokens per second, hold `capacity` tokens at most
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(capacity: u32, rate: u32) -> Self {
+        Self {
+            capacity: capacity as f64,
+            rate: rate as f64,
+            tokens: capacity as f64,
+            last: Instant::now(),
+        }
+    }
+
+    /// take a token, return false if there is no token left
+    pub fn try_acquire(&mut self) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// the categories of the inbound messages, each one has its own limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Chat,
+    Signaling,
+    Read,
+}
+
+impl Category {
+    pub fn of(msg_type: MsgType) -> Self {
+        match msg_type {
+            MsgType::Signal
+            | MsgType::SingleCallInvite
+            | MsgType::RejectSingleCall
+            | MsgType::AgreeSingleCall
+            | MsgType::SingleCallInviteNotAnswer
+            | MsgType::SingleCallInviteCancel
+            | MsgType::SingleCallOffer
+            | MsgType::Hangup
+            | MsgType::ConnectSingleCall
+            | MsgType::Candidate => Category::Signaling,
+            MsgType::Read => Category::Read,
+            _ => Category::Chat,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Category::Chat => "chat",
+            Category::Signaling => "signaling",
+            Category::Read => "read",
+        }
+    }
+}
+
+/// sync the count of the user with the cache after so many messages are accepted
+const SYNC_BATCH: u64 = 5;
+
+/// or after the interval, the messages are counted in the windows of one second
+const SYNC_INTERVAL: Duration = Duration::from_millis(250);
+
+/// the count of the user's messages in the current second across all gateways,
+/// it is synced with the cache in batches instead of counting every message
+#[derive(Debug, Default)]
+struct UserCounter {
+    /// the count returned by the cache at the last sync
+    synced: u64,
+    /// the messages accepted by this connection since the last sync
+    pending: u64,
+    last_sync: Option<Instant>,
+}
+
+impl UserCounter {
+    /// the estimated count of the user in the current window
+    fn estimate(&mut self) -> u64 {
+        // the window is switched
+        if self
+            .last_sync
+            .is_some_and(|t| t.elapsed() >= Duration::from_secs(1))
+        {
+            self.synced = 0;
+        }
+        self.synced + self.pending
+    }
+
+    fn need_sync(&self) -> bool {
+        self.pending >= SYNC_BATCH || self.last_sync.is_none_or(|t| t.elapsed() >= SYNC_INTERVAL)
+    }
+}
+
+/// the limits of a category for a connection
+struct Limiter {
+    limit: RateLimit,
+    bucket: TokenBucket,
+    counter: UserCounter,
+}
+
+impl Limiter {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            bucket: TokenBucket::new(limit.burst, limit.rate),
+            counter: UserCounter::default(),
+        }
+    }
+}
+
+/// limit the inbound messages of a connection,
+/// by the token buckets of the connection and the counters of the user in cache
+pub struct InboundLimiter {
+    user_id: String,
+    cache: Arc<dyn Cache>,
+    config: RateLimitConfig,
+    chat: Limiter,
+    signaling: Limiter,
+    read: Limiter,
+    /// the time of the recent violations
+    violations: VecDeque<Instant>,
+}
+
+impl InboundLimiter {
+    pub fn new(user_id: String, cache: Arc<dyn Cache>, config: &RateLimitConfig) -> Self {
+        Self {
+            user_id,
+            cache,
+            chat: Limiter::new(config.chat),
+            signaling: Limiter::new(config.signaling),
+            read: Limiter::new(config.read),
+            config: config.clone(),
+            violations: VecDeque::new(),
+        }
+    }
+
+    /// check the message against the limits of its category, return false if it is over limit,
+    /// the bucket of the connection is checked first, the cache is only visited in batches
+    pub async fn try_acquire(&mut self, category: Category) -> bool {
+        let limiter = match category {
+            Category::Chat => &mut self.chat,
+            Category::Signaling => &mut self.signaling,
+            Category::Read => &mut self.read,
+        };
+        if !limiter.bucket.try_acquire() {
+            return false;
+        }
+        // the user may send from several devices connected to different gateways
+        let counter = &mut limiter.counter;
+        if counter.estimate() >= limiter.limit.user_rate as u64 {
+            return false;
+        }
+        counter.pending += 1;
+        if !counter.need_sync() {
+            return true;
+        }
+
+        match self
+            .cache
+            .incr_rate_counter(&self.user_id, category.as_str(), 1, counter.pending)
+            .await
+        {
+            Ok(count) => counter.synced = count,
+            // do not block the user if the cache is not available
+            Err(e) => warn!("increase rate counter of {} error: {:?}", self.user_id, e),
+        }
+        counter.pending = 0;
+        counter.last_sync = Some(Instant::now());
+        true
+    }
+
+    /// record a violation, return true if the connection should be disconnected
+    pub fn violate(&mut self) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(self.config.violation_window);
+        while self
+            .violations
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > window)
+        {
+            self.violations.pop_front();
+        }
+        self.violations.push_back(now);
+        self.violations.len() > self.config.max_violations as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(2, 0);
+        assert!(bucket.try_acquire());
+        assert!(bucket.try_acquire());
+        assert!(!bucket.try_acquire());
+    }
+
+    #[test]
+    fn test_user_counter() {
+        let mut counter = UserCounter::default();
+        assert!(counter.need_sync());
+        counter.synced = 10;
+        counter.pending = 2;
+        counter.last_sync = Some(Instant::now());
+        assert_eq!(counter.estimate(), 12);
+        assert!(!counter.need_sync());
+        counter.pending = SYNC_BATCH;
+        assert!(counter.need_sync());
+
+        // the count of the last window is dropped
+        counter.last_sync = Some(Instant::now() - Duration::from_secs(1));
+        assert_eq!(counter.estimate(), SYNC_BATCH);
+        assert!(counter.need_sync());
+    }
+
+    #[test]
+    fn test_category() {
+        assert_eq!(Category::of(MsgType::SingleMsg), Category::Chat);
+        assert_eq!(Category::of(MsgType::Candidate), Category::Signaling);
+        assert_eq!(Category::of(MsgType::Signal), Category::Signaling);
+        assert_eq!(Category::of(MsgType::Read), Category::Read);
+    }
+}
@@ -1,29 +1,48 @@
 use std::result::Result;
 
 use synapse::health::{HealthServer, HealthService};
+use tokio::sync::mpsc;
 use tonic::transport::Server;
 use tonic::{async_trait, Request, Response, Status};
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
 use abi::config::{Component, Config};
 use abi::errors::Error;
 use abi::message::msg_service_server::MsgServiceServer;
 use abi::message::{
-    msg_service_server::MsgService, SendGroupMsgRequest, SendMsgRequest, SendMsgResponse,
+    msg_service_server::MsgService, DisconnectRequest, DrainRequest, GatewayStats,
+    GatewayStatsRequest, KnockOffRequest, SendGroupMsgRequest, SendMsgRequest, SendMsgResponse,
+    SendSignalRequest, UserConnectionsRequest, UserConnectionsResponse,
 };
 
 use crate::manager::Manager;
 
 pub struct MsgRpcService {
     manager: Manager,
+    /// trigger the draining of the gateway
+    drain_tx: mpsc::Sender<()>,
+    /// required by the admin rpc and the knock off from other gateways
+    admin_token: Option<String>,
 }
 
 impl MsgRpcService {
-    pub fn new(manager: Manager) -> Self {
-        Self { manager }
+    pub fn new(manager: Manager, drain_tx: mpsc::Sender<()>, admin_token: Option<String>) -> Self {
+        Self {
+            manager,
+            drain_tx,
+            admin_token,
+        }
     }
 
-    pub async fn start(manager: Manager, config: &Config) -> Result<(), Error> {
+    fn check_admin<T>(&self, request: &Request<T>) -> Result<(), Error> {
+        utils::check_admin_request(self.admin_token.as_deref(), request)
+    }
+
+    pub async fn start(
+        manager: Manager,
+        config: &Config,
+        drain_tx: mpsc::Sender<()>,
+    ) -> Result<(), Error> {
         // register service to service register center
         utils::register_service(config, Component::MessageGateway).await?;
         info!("<ws> rpc service register to service register center");
@@ -32,7 +51,15 @@ impl MsgRpcService {
         let health_service = HealthServer::new(HealthService::new());
         info!("<ws> rpc service health check started");
 
-        let service = Self::new(manager);
+        if config
+            .websocket
+            .admin_token
+            .as_deref()
+            .is_none_or(str::is_empty)
+        {
+            warn!("admin token is not set, the admin rpc and the knock off across gateways are disabled");
+        }
+        let service = Self::new(manager, drain_tx, config.websocket.admin_token.clone());
         let svc = MsgServiceServer::new(service);
         info!(
             "<ws> rpc service started at {}",
@@ -94,4 +121,76 @@ impl MsgService for MsgRpcService {
         let response = Response::new(SendMsgResponse {});
         Ok(response)
     }
+
+    /// knock off the device which is displaced by a new session on other gateway
+    async fn knock_off(
+        &self,
+        request: Request<KnockOffRequest>,
+    ) -> Result<Response<SendMsgResponse>, Status> {
+        self.check_admin(&request)?;
+        let req = request.into_inner();
+        let knock_off = req
+            .knock_off
+            .ok_or(Status::invalid_argument("knock off is empty"))?;
+        debug!("knock off user {} device {}", req.user_id, req.device_id);
+        self.manager
+            .knock_off(&req.user_id, &req.device_id, knock_off)
+            .await;
+        let response = Response::new(SendMsgResponse {});
+        Ok(response)
+    }
+
+    /// send the signal to the users connected to this gateway
+    async fn send_signal(
+        &self,
+        request: Request<SendSignalRequest>,
+    ) -> Result<Response<SendMsgResponse>, Status> {
+        let req = request.into_inner();
+        let msg = req
+            .message
+            .ok_or(Status::invalid_argument("message is empty"))?;
+        self.manager.send_signal_to_users(&req.receivers, &msg);
+        let response = Response::new(SendMsgResponse {});
+        Ok(response)
+    }
+
+    async fn get_stats(
+        &self,
+        request: Request<GatewayStatsRequest>,
+    ) -> Result<Response<GatewayStats>, Status> {
+        self.check_admin(&request)?;
+        Ok(Response::new(self.manager.stats()))
+    }
+
+    async fn get_user_connections(
+        &self,
+        request: Request<UserConnectionsRequest>,
+    ) -> Result<Response<UserConnectionsResponse>, Status> {
+        self.check_admin(&request)?;
+        let req = request.into_inner();
+        let connections = self.manager.user_connections(&req.user_id);
+        Ok(Response::new(UserConnectionsResponse { connections }))
+    }
+
+    async fn disconnect(
+        &self,
+        request: Request<DisconnectRequest>,
+    ) -> Result<Response<SendMsgResponse>, Status> {
+        self.check_admin(&request)?;
+        let req = request.into_inner();
+        self.manager.disconnect(&req.user_id, &req.device_id).await;
+        let response = Response::new(SendMsgResponse {});
+        Ok(response)
+    }
+
+    async fn drain(
+        &self,
+        request: Request<DrainRequest>,
+    ) -> Result<Response<SendMsgResponse>, Status> {
+        self.check_admin(&request)?;
+        // the drain is in progress if the channel is full
+        let _ = self.drain_tx.try_send(());
+        let response = Response::new(SendMsgResponse {});
+        Ok(response)
+    }
 }
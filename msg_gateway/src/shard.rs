@@ -0,0 +1,132 @@
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::SendError;
+use tokio::task::JoinHandle;
+
+/// dispatch the items to a fixed number of workers by the hash of the key,
+/// the items with the same key are handled by the same worker one by one,
+/// so they keep the order, while the items with different keys are handled concurrently
+pub struct Sharded<T> {
+    senders: Vec<mpsc::Sender<T>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<T: Send + 'static> Sharded<T> {
+    /// spawn `workers` tasks, each one has a queue of `capacity` items
+    pub fn new<F, Fut>(workers: usize, capacity: usize, handler: F) -> Self
+    where
+        F: Fn(T) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (senders, workers) = (0..workers.max(1))
+            .map(|_| {
+                let (tx, mut rx) = mpsc::channel(capacity);
+                let handler = handler.clone();
+                let worker = tokio::spawn(async move {
+                    while let Some(item) = rx.recv().await {
+                        handler(item).await;
+                    }
+                });
+                (tx, worker)
+            })
+            .unzip();
+        Self { senders, workers }
+    }
+
+    /// wait if the queue of the worker is full,
+    /// the callers share no dispatcher, so a full queue does not block the other workers
+    pub async fn dispatch(&self, key: &str, item: T) -> Result<(), SendError<T>> {
+        self.senders[self.index(key)].send(item).await
+    }
+
+    /// stop accepting items and wait for the workers to handle the queued ones
+    #[allow(dead_code)]
+    pub async fn close(self) {
+        drop(self.senders);
+        for worker in self.workers {
+            let _ = worker.await;
+        }
+    }
+
+    fn index(&self, key: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish() as usize % self.senders.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_order_per_key() {
+        let result = Arc::new(Mutex::new(Vec::new()));
+        let cloned = result.clone();
+        let sharded = Sharded::new(4, 16, move |(key, seq): (String, u32)| {
+            let result = cloned.clone();
+            async move {
+                // the later items finish faster, the order is kept by the worker
+                for _ in seq..10 {
+                    tokio::task::yield_now().await;
+                }
+                result.lock().unwrap().push((key, seq));
+            }
+        });
+        for seq in 0..10 {
+            for key in ["a", "b", "c"] {
+                sharded.dispatch(key, (key.to_string(), seq)).await.unwrap();
+            }
+        }
+        sharded.close().await;
+
+        let result = result.lock().unwrap();
+        assert_eq!(result.len(), 30);
+        for key in ["a", "b", "c"] {
+            let seqs: Vec<u32> = result
+                .iter()
+                .filter(|(k, _)| k == key)
+                .map(|(_, seq)| *seq)
+                .collect();
+            assert_eq!(seqs, (0..10).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_does_not_block_others() {
+        let (release_tx, release_rx) = tokio::sync::watch::channel(false);
+        let sharded = Arc::new(Sharded::new(2, 1, move |_: String| {
+            let mut release = release_rx.clone();
+            async move {
+                let _ = release.wait_for(|released| *released).await;
+            }
+        }));
+        let blocked = (0..100)
+            .map(|i| i.to_string())
+            .find(|key| sharded.index(key) == 0)
+            .unwrap();
+        let other = (0..100)
+            .map(|i| i.to_string())
+            .find(|key| sharded.index(key) == 1)
+            .unwrap();
+
+        // the first one is being handled, the second one fills the queue
+        sharded.dispatch(&blocked, blocked.clone()).await.unwrap();
+        sharded.dispatch(&blocked, blocked.clone()).await.unwrap();
+        let cloned = sharded.clone();
+        let cloned_key = blocked.clone();
+        let waiting =
+            tokio::spawn(async move { cloned.dispatch(&cloned_key, cloned_key.clone()).await });
+        // the other worker still accepts items
+        sharded.dispatch(&other, other.clone()).await.unwrap();
+        assert!(!waiting.is_finished());
+
+        release_tx.send(true).unwrap();
+        waiting.await.unwrap().unwrap();
+    }
+}
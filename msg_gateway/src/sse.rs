@@ -0,0 +1,309 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::ws::Message;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use futures::Stream;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{error, info, warn};
+
+use abi::errors::Error;
+use abi::message::{Msg, PlatformType};
+
+use crate::client::{now_millis, Client};
+use crate::codec::Codec;
+use crate::inbound::{Flow, Inbound};
+use crate::rate_limit::InboundLimiter;
+use crate::sync::SyncGate;
+use crate::unacked::UnackedWindow;
+use crate::ws_server::{AppState, WsServer, CLOSE_FLUSH_TIMEOUT};
+
+/// seconds, the ticket should be used right after it is issued
+const TICKET_EXPIRE: u64 = 30;
+
+/// the query parameters of the sse connect request
+#[derive(Deserialize)]
+pub struct SseParams {
+    /// the browsers can not set headers for the event source,
+    /// so a single-use ticket is carried by the query if the `Authorization` header is missing,
+    /// the token itself never appears in the url
+    pub ticket: Option<String>,
+}
+
+/// the upstream state of a sse connection
+pub struct SseSession {
+    // milliseconds timestamp of the last request of the client
+    last_active: Arc<AtomicU64>,
+    // the requests of a connection are handled one by one to keep the order
+    inbound: Mutex<Inbound>,
+}
+
+/// the fallback transport for the networks which block the websocket upgrade,
+/// the messages are pushed by server-sent events and sent by http post.
+///
+/// the connection is registered in the hub as a json client,
+/// the first event `open` carries the connection id, which the upstream requests carry in the path,
+/// the last event `close` carries the code and reason as the close frame of websocket.
+///
+/// the client can not answer the pings, it should send the heartbeat messages by the upstream,
+/// the connection is closed if no request is received for `max_missed_heartbeats` intervals
+pub struct SseServer;
+
+impl SseServer {
+    pub async fn connect_handler(
+        Path((pointer_id, platform)): Path<(String, i32)>,
+        Query(params): Query<SseParams>,
+        headers: HeaderMap,
+        State(state): State<AppState>,
+    ) -> Response {
+        // the client should reconnect to another gateway
+        if state.manager.is_draining() {
+            return (StatusCode::SERVICE_UNAVAILABLE, "draining").into_response();
+        }
+        let result = match (WsServer::bearer_token(&headers), params.ticket) {
+            (Some(token), _) => WsServer::verify_token(token, &state.jwt_secret),
+            (None, Some(ticket)) => match state.manager.cache.take_connect_ticket(&ticket).await {
+                Ok(Some(user_id)) => Ok(user_id),
+                Ok(None) => Err(Error::unauthorized_with_details(
+                    "ticket is invalid or expired",
+                )),
+                Err(e) => Err(e),
+            },
+            (None, None) => Err(Error::unauthorized_with_details("token is missing")),
+        };
+        let user_id = match result {
+            Ok(user_id) => user_id,
+            Err(err) => return err.into_response(),
+        };
+        let platform = PlatformType::try_from(platform).unwrap_or_default();
+
+        let (event_tx, event_rx) = mpsc::channel(state.ws_config.outbound_queue_size);
+        tokio::spawn(Self::connection(
+            user_id, pointer_id, platform, event_tx, state,
+        ));
+        Sse::new(Self::events(event_rx)).into_response()
+    }
+
+    /// issue a single-use ticket for the event source which can not carry the token in the headers,
+    /// the ticket is put in the query of the connect request and expires soon
+    pub async fn ticket_handler(
+        headers: HeaderMap,
+        State(state): State<AppState>,
+    ) -> Result<String, Error> {
+        let token = WsServer::bearer_token(&headers)
+            .ok_or_else(|| Error::unauthorized_with_details("token is missing"))?;
+        let user_id = WsServer::verify_token(token, &state.jwt_secret)?;
+        let ticket = nanoid::nanoid!(32);
+        state
+            .manager
+            .cache
+            .save_connect_ticket(&ticket, &user_id, TICKET_EXPIRE)
+            .await?;
+        Ok(ticket)
+    }
+
+    /// the upstream of the connection, the body is a json message,
+    /// the replies like rejections are pushed by the events
+    pub async fn send_handler(
+        Path(conn_id): Path<String>,
+        headers: HeaderMap,
+        State(state): State<AppState>,
+        Json(msg): Json<Msg>,
+    ) -> Result<(), Error> {
+        let token = WsServer::bearer_token(&headers)
+            .ok_or_else(|| Error::unauthorized_with_details("token is missing"))?;
+        let user_id = WsServer::verify_token(token, &state.jwt_secret)?;
+        let session = state
+            .sse_sessions
+            .get(&conn_id)
+            .map(|session| session.clone())
+            .ok_or_else(|| Error::not_found_with_details(conn_id))?;
+
+        let mut inbound = session.inbound.lock().await;
+        if inbound.user_id != user_id {
+            return Err(Error::unauthorized_with_details("connection mismatch"));
+        }
+        session.last_active.store(now_millis(), Ordering::Relaxed);
+        match inbound.handle(msg).await {
+            Flow::Break => Err(Error::internal_with_details("connection is closed")),
+            _ => Ok(()),
+        }
+    }
+
+    fn events(event_rx: mpsc::Receiver<Event>) -> impl Stream<Item = Result<Event, Infallible>> {
+        futures::stream::unfold(event_rx, |mut rx| async move {
+            rx.recv().await.map(|event| (Ok(event), rx))
+        })
+    }
+
+    async fn connection(
+        user_id: String,
+        pointer_id: String,
+        platform: PlatformType,
+        event_tx: mpsc::Sender<Event>,
+        app_state: AppState,
+    ) {
+        // the events are text, the messages are always json
+        let codec = Codec::Json;
+        let conn_id = nanoid::nanoid!();
+        // tell the client the id of the connection
+        if event_tx
+            .send(Event::default().event("open").data(&conn_id))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        info!("sse client {} connected, user id : {}", pointer_id, user_id);
+
+        let (out_tx, out_rx) = mpsc::channel(app_state.ws_config.outbound_queue_size);
+        let (notify_sender, notify_receiver) = mpsc::channel(1);
+        let mut hub = app_state.manager.clone();
+        let unacked = Arc::new(UnackedWindow::new(
+            app_state.ws_config.ack_window,
+            Duration::from_millis(app_state.ws_config.ack_timeout),
+            app_state.ws_config.ack_max_retries,
+        ));
+        let last_active = Arc::new(AtomicU64::new(now_millis()));
+        let sync_gate = Arc::new(SyncGate::default());
+        let client = Client {
+            user_id: user_id.clone(),
+            device_id: pointer_id.clone(),
+            conn_id: conn_id.clone(),
+            sender: out_tx.clone(),
+            platform,
+            codec,
+            notify_sender,
+            full_since: AtomicU64::new(0),
+            slow_consumer_timeout: Duration::from_millis(app_state.ws_config.slow_consumer_timeout),
+            unacked: unacked.clone(),
+            last_active: last_active.clone(),
+            sync_gate: sync_gate.clone(),
+        };
+        hub.register(user_id.clone(), client).await;
+
+        let session = SseSession {
+            last_active: last_active.clone(),
+            inbound: Mutex::new(Inbound {
+                hub: hub.clone(),
+                sender: out_tx.clone(),
+                user_id: user_id.clone(),
+                device_id: pointer_id.clone(),
+                platform,
+                codec,
+                unacked: unacked.clone(),
+                sync_gate: sync_gate.clone(),
+                limiter: InboundLimiter::new(
+                    user_id.clone(),
+                    hub.cache.clone(),
+                    &app_state.ws_config.rate_limit,
+                ),
+            }),
+        };
+        app_state
+            .sse_sessions
+            .insert(conn_id.clone(), Arc::new(session));
+
+        let mut write_task = tokio::spawn(Self::write(out_rx, event_tx));
+
+        // the client does not answer the pings, it is alive if it keeps sending requests,
+        // the pings keep the proxies from closing the idle connection
+        let cloned_tx = out_tx.clone();
+        let cloned_hub = hub.clone();
+        let cloned_user_id = user_id.clone();
+        let interval = Duration::from_secs(app_state.ws_config.heartbeat_interval.max(1));
+        let timeout = interval * app_state.ws_config.max_missed_heartbeats.max(1);
+        let mut ping_task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let idle = now_millis().saturating_sub(last_active.load(Ordering::Relaxed));
+                if idle > timeout.as_millis() as u64 {
+                    warn!("sse client of {} is idle for {}ms", cloned_user_id, idle);
+                    break;
+                }
+                // the queue is still full after a whole interval, evict the slow client
+                if cloned_tx.try_send(Message::Ping(Vec::new())).is_err() {
+                    break;
+                }
+                cloned_hub.heartbeat(&cloned_user_id).await;
+            }
+        });
+
+        let mut watch_task = tokio::spawn(WsServer::watch_knock_off(
+            pointer_id.clone(),
+            notify_receiver,
+            out_tx.clone(),
+            codec,
+        ));
+        let mut retry_task = tokio::spawn(WsServer::retry_unacked(unacked, out_tx, sync_gate));
+
+        let mut need_unregister = true;
+        tokio::select! {
+            _ = (&mut ping_task) => {},
+            flush = (&mut watch_task) => {
+                need_unregister = false;
+                ping_task.abort();
+                retry_task.abort();
+                // wait for the knock off events to be written
+                if flush.unwrap_or_default() {
+                    let timeout = Duration::from_secs(CLOSE_FLUSH_TIMEOUT);
+                    let _ = tokio::time::timeout(timeout, &mut write_task).await;
+                }
+            },
+            _ = (&mut write_task) => {},
+            _ = (&mut retry_task) => {},
+        }
+        ping_task.abort();
+        watch_task.abort();
+        write_task.abort();
+        retry_task.abort();
+
+        app_state.sse_sessions.remove(&conn_id);
+        if need_unregister {
+            hub.unregister(user_id, pointer_id, conn_id).await;
+        }
+    }
+
+    /// convert the frames of the outbound queue to events,
+    /// finish if the client is gone or the close frame is written
+    async fn write(mut out_rx: mpsc::Receiver<Message>, event_tx: mpsc::Sender<Event>) {
+        loop {
+            let msg = tokio::select! {
+                msg = out_rx.recv() => msg,
+                // the response stream is dropped
+                _ = event_tx.closed() => break,
+            };
+            let Some(msg) = msg else {
+                break;
+            };
+            let (event, is_close) = match msg {
+                Message::Text(text) => (Event::default().data(text), false),
+                Message::Ping(_) => (Event::default().comment("ping"), false),
+                Message::Close(frame) => {
+                    let (code, reason) = frame
+                        .map(|f| (f.code, f.reason.into_owned()))
+                        .unwrap_or_default();
+                    let data = json!({ "code": code, "reason": reason }).to_string();
+                    (Event::default().event("close").data(data), true)
+                }
+                // the codec is json, there is no binary frame
+                msg => {
+                    error!("unexpected frame of sse connection: {:?}", msg);
+                    continue;
+                }
+            };
+            if event_tx.send(event).await.is_err() || is_close {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Mutex;
+
+use axum::extract::ws::Message;
+
+/// the max live messages held while syncing,
+/// the others are left to the retry of un-acked messages
+const MAX_HELD: usize = 1024;
+
+/// hold the live messages while the connection is syncing the missing messages,
+/// they are released after the sync complete marker
+#[derive(Debug, Default)]
+pub struct SyncGate {
+    held: Mutex<Option<Vec<(i64, Message)>>>,
+}
+
+impl SyncGate {
+    /// start holding the live messages, return false if the connection is syncing already
+    pub fn close(&self) -> bool {
+        let mut held = self.held.lock().unwrap();
+        if held.is_some() {
+            return false;
+        }
+        *held = Some(Vec::new());
+        true
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        self.held.lock().unwrap().is_some()
+    }
+
+    /// hold the frame if the connection is syncing,
+    /// return the frame back if it should be sent directly, or the gate is full
+    pub fn hold(&self, seq: i64, frame: Message) -> Result<(), Message> {
+        match self.held.lock().unwrap().as_mut() {
+            Some(held) if held.len() < MAX_HELD => {
+                held.push((seq, frame));
+                Ok(())
+            }
+            _ => Err(frame),
+        }
+    }
+
+    /// stop holding, return the held frames except the ones covered by the sync
+    pub fn open(&self, synced_seq: i64) -> Vec<Message> {
+        self.held
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(seq, _)| *seq <= 0 || *seq > synced_seq)
+            .map(|(_, frame)| frame)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_gate() {
+        let gate = SyncGate::default();
+        assert!(gate.hold(1, Message::Binary(vec![1])).is_err());
+
+        assert!(gate.close());
+        assert!(!gate.close());
+        assert!(gate.hold(1, Message::Binary(vec![1])).is_ok());
+        assert!(gate.hold(0, Message::Binary(vec![0])).is_ok());
+        assert!(gate.hold(3, Message::Binary(vec![3])).is_ok());
+
+        // the message with seq 1 is sent by the sync already
+        let frames = gate.open(2);
+        assert_eq!(
+            frames,
+            vec![Message::Binary(vec![0]), Message::Binary(vec![3])]
+        );
+        assert!(!gate.is_syncing());
+    }
+}
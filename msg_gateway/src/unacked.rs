@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use abi::message::Msg;
+use axum::extract::ws::Message;
+
+/// the delay of a retry never exceeds it, however many times the message is retried
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// the message which is sent to the client but not acked yet
+#[derive(Debug)]
+struct Unacked {
+    /// the message without content, used to report delivery
+    msg: Msg,
+    /// the encoded frame, used to retry
+    frame: Message,
+    retries: u32,
+    next_retry: Instant,
+}
+
+/// the un-acked messages of a connection, keyed by seq
+#[derive(Debug)]
+pub struct UnackedWindow {
+    inner: Mutex<BTreeMap<i64, Unacked>>,
+    capacity: usize,
+    timeout: Duration,
+    max_retries: u32,
+}
+
+impl UnackedWindow {
+    pub fn new(capacity: usize, timeout: Duration, max_retries: u32) -> Self {
+        Self {
+            inner: Mutex::new(BTreeMap::new()),
+            capacity,
+            timeout,
+            max_retries,
+        }
+    }
+
+    /// record the message sent to the client,
+    /// the oldest one is dropped if the window is full, the client can pull it from inbox
+    pub fn push(&self, msg: &Msg, frame: Message) {
+        let mut msg = msg.clone();
+        msg.content.clear();
+        let seq = msg.seq;
+        let unacked = Unacked {
+            msg,
+            frame,
+            retries: 0,
+            next_retry: Instant::now() + self.timeout,
+        };
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.insert(seq, unacked);
+        while inner.len() > self.capacity {
+            inner.pop_first();
+        }
+    }
+
+    /// remove the acked message, return it if it is waiting for ack
+    pub fn ack(&self, seq: i64) -> Option<Msg> {
+        self.inner
+            .lock()
+            .unwrap()
+            .remove(&seq)
+            .map(|unacked| unacked.msg)
+    }
+
+    /// the messages which need to be sent again,
+    /// the delay doubles on each retry, and the message is dropped after max retries
+    pub fn due(&self) -> Vec<Message> {
+        let now = Instant::now();
+        let mut result = Vec::new();
+        let mut inner = self.inner.lock().unwrap();
+        inner.retain(|_, unacked| {
+            if unacked.next_retry > now {
+                return true;
+            }
+            if unacked.retries >= self.max_retries {
+                return false;
+            }
+            unacked.retries += 1;
+            unacked.next_retry = now + self.backoff(unacked.retries);
+            result.push(unacked.frame.clone());
+            true
+        });
+        result
+    }
+
+    /// the delay before the next retry, doubled on each retry and capped
+    fn backoff(&self, retries: u32) -> Duration {
+        2u32.checked_pow(retries)
+            .map_or(MAX_RETRY_DELAY, |factor| {
+                self.timeout.saturating_mul(factor)
+            })
+            .min(MAX_RETRY_DELAY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ack_and_capacity() {
+        let window = UnackedWindow::new(2, Duration::from_secs(1), 3);
+        for seq in 1..=3 {
+            let msg = Msg {
+                seq,
+                content: vec![1, 2, 3],
+                ..Default::default()
+            };
+            window.push(&msg, Message::Binary(vec![seq as u8]));
+        }
+
+        // the oldest one is dropped
+        assert!(window.ack(1).is_none());
+        let msg = window.ack(2).unwrap();
+        assert_eq!(msg.seq, 2);
+        assert!(msg.content.is_empty());
+        assert!(window.ack(2).is_none());
+    }
+
+    #[test]
+    fn test_due() {
+        let window = UnackedWindow::new(8, Duration::ZERO, 2);
+        let msg = Msg {
+            seq: 1,
+            ..Default::default()
+        };
+        window.push(&msg, Message::Binary(vec![1]));
+
+        assert_eq!(window.due(), vec![Message::Binary(vec![1])]);
+        assert_eq!(window.due(), vec![Message::Binary(vec![1])]);
+        // reach the max retries
+        assert!(window.due().is_empty());
+        assert!(window.ack(1).is_none());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let window = UnackedWindow::new(8, Duration::from_secs(3), 64);
+        assert_eq!(window.backoff(1), Duration::from_secs(6));
+        assert_eq!(window.backoff(4), Duration::from_secs(48));
+        assert_eq!(window.backoff(5), MAX_RETRY_DELAY);
+        // 2^40 overflows u32
+        assert_eq!(window.backoff(40), MAX_RETRY_DELAY);
+    }
+}
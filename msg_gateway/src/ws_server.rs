@@ -1,39 +1,84 @@
 use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use axum::extract::ws::CloseFrame;
-use axum::extract::{Path, State, WebSocketUpgrade};
+use axum::extract::{Path, Query, State, WebSocketUpgrade};
+use axum::http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL};
+use axum::http::{HeaderMap, StatusCode};
 use axum::response::IntoResponse;
-use axum::routing::get;
+use axum::routing::{delete, get, post};
 use axum::{
     extract::ws::{Message, WebSocket},
-    Router,
+    Json, Router,
 };
+use dashmap::DashMap;
+use futures::stream::SplitStream;
 use futures::{SinkExt, StreamExt};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc, RwLock};
+use tokio::signal;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
 use tonic::transport::Channel;
 use tracing::{error, info, warn};
 
-use abi::config::Config;
+use abi::config::{Component, Config, WsServerConfig};
 use abi::errors::Error;
-use abi::message::{Msg, PlatformType};
-use synapse::service::{Scheme, ServiceInstance, ServiceRegistryClient};
+use abi::message::{
+    Connection, GatewayStats, KnockOff, KnockOffReason, Msg, MsgType, PlatformType,
+};
+use synapse::service::client::ServiceClient;
+use synapse::service::{Scheme, ServiceInstance, ServiceRegistryClient, ServiceStatus};
 
-use crate::client::Client;
+use crate::client::{now_millis, Client};
+use crate::codec::Codec;
+use crate::inbound::{Flow, Inbound};
 use crate::manager::Manager;
+use crate::rate_limit::InboundLimiter;
 use crate::rpc::MsgRpcService;
+use crate::sse::{SseServer, SseSession};
+use crate::sync::SyncGate;
+use crate::unacked::UnackedWindow;
 
-pub const HEART_BEAT_INTERVAL: u64 = 30;
 pub const KNOCK_OFF_CODE: u16 = 4001;
 pub const UNAUTHORIZED_CODE: u16 = 4002;
+/// the gateway is shutting down, the reason of the close frame is the gateway to reconnect
+pub const DRAIN_CODE: u16 = 4003;
+/// the client keeps sending messages over the rate limits
+pub const RATE_LIMITED_CODE: u16 = 4004;
+/// the sub protocol which carries the token in the handshake
+pub const AUTH_PROTOCOL: &str = "sandcat.auth";
+/// seconds, wait for the auth frame if the token is not in the handshake
+pub const AUTH_TIMEOUT: u64 = 10;
+/// seconds, wait for the knock off frames to be written before closing the connection
+pub const CLOSE_FLUSH_TIMEOUT: u64 = 3;
+/// milliseconds, how often to check the un-acked messages
+pub const ACK_CHECK_INTERVAL: u64 = 1000;
 
 #[derive(Clone)]
 pub struct AppState {
-    manager: Manager,
-    jwt_secret: String,
+    pub(crate) manager: Manager,
+    pub(crate) jwt_secret: String,
+    pub(crate) ws_config: Arc<WsServerConfig>,
+    pub(crate) drain_tx: mpsc::Sender<()>,
+    /// the sse connections, the upstream requests find the connection by its id
+    pub(crate) sse_sessions: Arc<DashMap<String, Arc<SseSession>>>,
+}
+
+/// the query parameters of the connect request
+#[derive(Deserialize)]
+pub struct ConnectParams {
+    /// the wire format of the frames, bincode by default for the legacy clients
+    #[serde(default)]
+    pub codec: Codec,
+}
+
+/// the first frame sent by the client if the token is not in the handshake
+#[derive(Deserialize)]
+pub struct AuthFrame {
+    pub token: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -41,12 +86,18 @@ pub struct Claims {
     pub sub: String,
     pub exp: u64,
     pub iat: u64,
+    /// the version of the claims, see the api for the changes of each version
+    #[serde(default)]
+    pub ver: u32,
 }
 
+/// the tokens of the older versions carry the user name as subject
+const CLAIMS_VERSION: u32 = 1;
+
 pub struct WsServer;
 
 impl WsServer {
-    async fn register_service(config: &Config) -> Result<(), Error> {
+    async fn register_service(config: &Config, status: ServiceStatus) -> Result<(), Error> {
         // register service to service register center
         let addr = format!(
             "{}://{}:{}",
@@ -63,51 +114,54 @@ impl WsServer {
             version: "".to_string(),
             metadata: Default::default(),
             health_check: None,
-            status: 0,
+            status: status as i32,
             scheme: Scheme::from(config.rpc.db.protocol.as_str()) as i32,
         };
-        client.register_service(service).await.unwrap();
+        client.register_service(service).await?;
         Ok(())
     }
 
-    async fn test(State(state): State<AppState>) -> Result<String, Error> {
-        let mut description = String::new();
-
-        state.manager.hub.iter().for_each(|entry| {
-            let user_id = entry.key();
-            let platforms = entry.value();
-            description.push_str(&format!("UserID: {}\n", user_id));
-            platforms.iter().for_each(|platform_entry| {
-                let platform_type = platform_entry.key();
-                let client = platform_entry.value();
-                description.push_str(&format!(
-                    "  Platform: {:?}, PlatformID: {}\n",
-                    platform_type, client.platform_id
-                ));
-            });
-        });
-        Ok(description)
-    }
-
     pub async fn start(config: Config) {
-        let (tx, rx) = mpsc::channel(1024);
-        let hub = Manager::new(tx, &config).await;
-        let mut cloned_hub = hub.clone();
-        tokio::spawn(async move {
-            cloned_hub.run(rx).await;
+        let (drain_tx, mut drain_rx) = mpsc::channel(1);
+        let hub = Manager::new(&config).await;
+        hub.run();
+        hub.clear_registry().await;
+        let cloned_hub = hub.clone();
+        let lease_ttl = config.websocket.gateway_lease_ttl;
+        let lease = tokio::spawn(async move {
+            cloned_hub.keep_lease(lease_ttl).await;
         });
         let app_state = AppState {
             manager: hub.clone(),
             jwt_secret: config.server.jwt_secret.clone(),
+            ws_config: Arc::new(config.websocket.clone()),
+            drain_tx: drain_tx.clone(),
+            sse_sessions: Arc::new(DashMap::new()),
         };
 
         // run axum server
         let router = Router::new()
             .route(
-                "/ws/:user_id/conn/:pointer_id/:platform/:token",
+                "/ws/:pointer_id/conn/:platform",
                 get(Self::websocket_handler),
             )
-            .route("/test", get(Self::test))
+            // the fallback transport for the networks which block the websocket upgrade
+            .route(
+                "/sse/:pointer_id/conn/:platform",
+                get(SseServer::connect_handler),
+            )
+            .route("/sse/ticket", post(SseServer::ticket_handler))
+            .route("/sse/:conn_id", post(SseServer::send_handler))
+            .route("/admin/stats", get(Self::stats_handler))
+            .route(
+                "/admin/users/:user_id",
+                get(Self::connections_handler).delete(Self::disconnect_user_handler),
+            )
+            .route(
+                "/admin/users/:user_id/:device_id",
+                delete(Self::disconnect_device_handler),
+            )
+            .route("/admin/drain", post(Self::drain_handler))
             .with_state(app_state);
         let addr = format!("{}:{}", config.websocket.host, config.websocket.port);
 
@@ -118,188 +172,558 @@ impl WsServer {
         });
 
         // register websocket service to consul
-        Self::register_service(&config).await.unwrap();
+        Self::register_service(&config, ServiceStatus::Up)
+            .await
+            .unwrap();
 
-        let config = config.clone();
+        let cloned_config = config.clone();
+        let cloned_hub = hub.clone();
         let mut rpc = tokio::spawn(async move {
             // start rpc server
-            MsgRpcService::start(hub, &config).await.unwrap();
+            MsgRpcService::start(cloned_hub, &cloned_config, drain_tx)
+                .await
+                .unwrap();
         });
         tokio::select! {
             _ = (&mut ws) => ws.abort(),
             _ = (&mut rpc) => rpc.abort(),
+            _ = Self::shutdown_signal(&mut drain_rx) => {
+                Self::drain(&hub, &config).await;
+                ws.abort();
+                rpc.abort();
+            }
+        }
+        lease.abort();
+    }
+
+    /// wait for SIGTERM or the drain request from admin api
+    async fn shutdown_signal(drain_rx: &mut mpsc::Receiver<()>) {
+        #[cfg(unix)]
+        let terminate = async {
+            match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+                Ok(mut sig) => {
+                    sig.recv().await;
+                }
+                Err(e) => {
+                    error!("listen SIGTERM error: {}", e);
+                    std::future::pending::<()>().await;
+                }
+            }
+        };
+        #[cfg(not(unix))]
+        let terminate = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = terminate => info!("receive SIGTERM, start draining"),
+            _ = drain_rx.recv() => info!("receive drain request, start draining"),
         }
     }
 
-    fn verify_token(token: String, jwt_secret: &String) -> Result<(), Error> {
-        if let Err(err) = decode::<Claims>(
-            &token,
+    /// stop accepting connections and close the existing ones gradually,
+    /// the rpc service is deregistered at last,
+    /// because the pusher still needs it to deliver messages to the remaining connections
+    async fn drain(manager: &Manager, config: &Config) {
+        manager.start_drain();
+        if let Err(e) = Self::register_service(config, ServiceStatus::Down).await {
+            error!("deregister websocket service error: {:?}", e);
+        }
+
+        let alternatives = match Self::alternative_gateways(config).await {
+            Ok(list) => list,
+            Err(e) => {
+                error!("query alternative gateways error: {:?}", e);
+                Vec::new()
+            }
+        };
+        manager
+            .drain(
+                &alternatives,
+                Duration::from_secs(config.websocket.drain_window),
+            )
+            .await;
+
+        if let Err(e) = utils::deregister_service(config, Component::MessageGateway).await {
+            error!("deregister rpc service error: {:?}", e);
+        }
+        info!("drain finished");
+    }
+
+    /// the websocket address of other gateways from the service center
+    async fn alternative_gateways(config: &Config) -> Result<Vec<String>, Error> {
+        let mut client = ServiceClient::builder()
+            .server_host(config.service_center.host.clone())
+            .server_port(config.service_center.port)
+            .connect_timeout(Duration::from_millis(config.service_center.timeout))
+            .build()
+            .await
+            .map_err(|e| Error::internal_with_details(e.to_string()))?;
+        let list = client
+            .query_with_name(config.websocket.name.clone())
+            .await
+            .map_err(|e| Error::internal_with_details(e.to_string()))?
+            .into_iter()
+            .filter(|service| {
+                service.active == ServiceStatus::Up as i32
+                    && (service.address != config.websocket.host
+                        || service.port != config.websocket.port as i32)
+            })
+            .map(|service| {
+                format!(
+                    "{}://{}:{}",
+                    config.websocket.protocol, service.address, service.port
+                )
+            })
+            .collect();
+        Ok(list)
+    }
+
+    /// the connection counts of this gateway,
+    /// the admin requests should carry the admin token as bearer token
+    async fn stats_handler(
+        State(state): State<AppState>,
+        headers: HeaderMap,
+    ) -> Result<Json<GatewayStats>, Error> {
+        Self::check_admin(&state, &headers)?;
+        Ok(Json(state.manager.stats()))
+    }
+
+    async fn connections_handler(
+        State(state): State<AppState>,
+        headers: HeaderMap,
+        Path(user_id): Path<String>,
+    ) -> Result<Json<Vec<Connection>>, Error> {
+        Self::check_admin(&state, &headers)?;
+        Ok(Json(state.manager.user_connections(&user_id)))
+    }
+
+    async fn disconnect_user_handler(
+        State(state): State<AppState>,
+        headers: HeaderMap,
+        Path(user_id): Path<String>,
+    ) -> Result<(), Error> {
+        Self::check_admin(&state, &headers)?;
+        state.manager.disconnect(&user_id, "").await;
+        Ok(())
+    }
+
+    async fn disconnect_device_handler(
+        State(state): State<AppState>,
+        headers: HeaderMap,
+        Path((user_id, device_id)): Path<(String, String)>,
+    ) -> Result<(), Error> {
+        Self::check_admin(&state, &headers)?;
+        state.manager.disconnect(&user_id, &device_id).await;
+        Ok(())
+    }
+
+    /// trigger draining
+    async fn drain_handler(
+        State(state): State<AppState>,
+        headers: HeaderMap,
+    ) -> Result<String, Error> {
+        Self::check_admin(&state, &headers)?;
+        // the drain is in progress if the channel is full
+        let _ = state.drain_tx.try_send(());
+        Ok("draining".to_string())
+    }
+
+    fn check_admin(state: &AppState, headers: &HeaderMap) -> Result<(), Error> {
+        let admin_token = state.ws_config.admin_token.as_deref();
+        if utils::check_admin_token(admin_token, Self::bearer_token(headers)) {
+            Ok(())
+        } else {
+            Err(Error::unauthorized_with_details("admin token is invalid"))
+        }
+    }
+
+    /// the token of the `Authorization: Bearer <token>` header
+    pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+        headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(utils::bearer_token)
+    }
+
+    /// verify the token and return the user id from the claims
+    pub(crate) fn verify_token(token: &str, jwt_secret: &str) -> Result<String, Error> {
+        match decode::<Claims>(
+            token,
             &DecodingKey::from_secret(jwt_secret.as_bytes()),
             &Validation::default(),
         ) {
-            return Err(Error::unauthorized(err, "/ws"));
+            Ok(data) if data.claims.ver >= CLAIMS_VERSION => Ok(data.claims.sub),
+            Ok(_) => Err(Error::unauthorized_with_details(
+                "token is issued by an older version, please login again",
+            )),
+            Err(err) => Err(Error::unauthorized(err, "/ws")),
+        }
+    }
+
+    /// the client sends `Sec-WebSocket-Protocol: sandcat.auth, <token>`
+    fn token_from_protocols(protocols: &str) -> Option<String> {
+        let protocols: Vec<&str> = protocols.split(',').map(str::trim).collect();
+        if !protocols.contains(&AUTH_PROTOCOL) {
+            return None;
+        }
+        protocols
+            .into_iter()
+            .find(|p| *p != AUTH_PROTOCOL && !p.is_empty())
+            .map(String::from)
+    }
+
+    /// read the token from the first frame if it is not carried by the handshake
+    async fn read_auth_frame(ws_rx: &mut SplitStream<WebSocket>) -> Option<String> {
+        let frame = tokio::time::timeout(Duration::from_secs(AUTH_TIMEOUT), ws_rx.next())
+            .await
+            .ok()??
+            .ok()?;
+        let auth: AuthFrame = match frame {
+            Message::Text(text) => serde_json::from_str(&text).ok()?,
+            Message::Binary(b) => serde_json::from_slice(&b).ok()?,
+            _ => return None,
+        };
+        Some(auth.token)
+    }
+
+    /// tell the client why it is knocked off before closing the connection
+    fn send_knock_off(sender: &mpsc::Sender<Message>, codec: Codec, knock_off: KnockOff) {
+        let content = match codec.encode_content(&knock_off) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("knock off serialize error: {:?}", e);
+                return;
+            }
+        };
+        let msg = Msg {
+            msg_type: MsgType::KnockOff as i32,
+            content,
+            ..Default::default()
+        };
+        let frame = match codec.encode(&msg) {
+            Ok(frame) => frame,
+            Err(e) => {
+                error!("msg serialize error: {:?}", e);
+                return;
+            }
+        };
+        // the reason is optional, the close frame below still tells the client
+        if let Err(e) = sender.try_send(frame) {
+            error!("send knock off reason to client error: {}", e);
+        }
+    }
+
+    /// watch knock off signal, return true if the close frame is queued
+    pub(crate) async fn watch_knock_off(
+        pointer_id: String,
+        mut notify_receiver: mpsc::Receiver<KnockOff>,
+        sender: mpsc::Sender<Message>,
+        codec: Codec,
+    ) -> bool {
+        // the sender is dropped means the client is evicted
+        let Some(knock_off) = notify_receiver.recv().await else {
+            info!("client {} evicted", pointer_id);
+            return false;
+        };
+        let reason = KnockOffReason::try_from(knock_off.reason).unwrap_or_default();
+        info!("client {} knock off: {:?}", pointer_id, reason);
+        let frame = if reason == KnockOffReason::Drain {
+            // tell the client which gateway to reconnect
+            CloseFrame {
+                code: DRAIN_CODE,
+                reason: Cow::Owned(knock_off.gateway),
+            }
+        } else {
+            Self::send_knock_off(&sender, codec, knock_off);
+            CloseFrame {
+                code: KNOCK_OFF_CODE,
+                reason: Cow::Borrowed(reason.as_str_name()),
+            }
+        };
+        // send knock off signal to ws server,
+        // a full queue means a slow client, close the connection without waiting for it
+        if let Err(e) = sender.try_send(Message::Close(Some(frame))) {
+            error!("send knock off signal to client error: {}", e);
+            return false;
+        }
+        true
+    }
+
+    /// send the un-acked messages again
+    pub(crate) async fn retry_unacked(
+        unacked: Arc<UnackedWindow>,
+        sender: mpsc::Sender<Message>,
+        gate: Arc<SyncGate>,
+    ) {
+        let mut interval = tokio::time::interval(Duration::from_millis(ACK_CHECK_INTERVAL));
+        loop {
+            interval.tick().await;
+            // do not interleave with the syncing messages
+            if gate.is_syncing() {
+                continue;
+            }
+            for frame in unacked.due() {
+                match sender.try_send(frame) {
+                    Ok(()) => {}
+                    // the rest will be retried later
+                    Err(TrySendError::Full(_)) => break,
+                    Err(e) => {
+                        error!("retry message error: {:?}", e);
+                        return;
+                    }
+                }
+            }
         }
-        Ok(())
     }
 
     pub async fn websocket_handler(
-        Path((user_id, pointer_id, platform, token)): Path<(String, String, i32, String)>,
+        Path((pointer_id, platform)): Path<(String, i32)>,
+        Query(params): Query<ConnectParams>,
+        headers: HeaderMap,
         ws: WebSocketUpgrade,
         State(state): State<AppState>,
     ) -> impl IntoResponse {
+        // stop accepting upgrades, the client should reconnect to another gateway
+        if state.manager.is_draining() {
+            return (StatusCode::SERVICE_UNAVAILABLE, "draining").into_response();
+        }
         let platform = PlatformType::try_from(platform).unwrap_or_default();
+        let token = headers
+            .get(SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|v| v.to_str().ok())
+            .and_then(Self::token_from_protocols);
+        // the client requires the server to select one of the protocols
+        let ws = if token.is_some() {
+            ws.protocols([AUTH_PROTOCOL])
+        } else {
+            ws
+        };
         ws.on_upgrade(move |socket| {
-            Self::websocket(user_id, pointer_id, token, platform, socket, state)
+            Self::websocket(pointer_id, token, platform, params.codec, socket, state)
         })
+        .into_response()
     }
 
     pub async fn websocket(
-        user_id: String,
         pointer_id: String,
-        token: String,
+        token: Option<String>,
         platform: PlatformType,
+        codec: Codec,
         ws: WebSocket,
         app_state: AppState,
     ) {
+        let (mut ws_tx, mut ws_rx) = ws.split();
+        // the token is carried by the handshake or the first frame
+        let token = match token {
+            Some(token) => Some(token),
+            None => Self::read_auth_frame(&mut ws_rx).await,
+        };
+        // validate token, the user id comes from the claims
+        let result = match token {
+            Some(token) => Self::verify_token(&token, &app_state.jwt_secret),
+            None => Err(Error::unauthorized_with_details("token is missing")),
+        };
+        let user_id = match result {
+            Ok(user_id) => user_id,
+            Err(err) => {
+                warn!("verify token error: {:?}", err);
+                if let Err(e) = ws_tx
+                    .send(Message::Close(Some(CloseFrame {
+                        code: UNAUTHORIZED_CODE,
+                        reason: Cow::Owned("unauthorized".to_string()),
+                    })))
+                    .await
+                {
+                    error!("send verify failed to client error: {}", e);
+                }
+                return;
+            }
+        };
         tracing::info!(
             "client {} connected, user id : {}",
             user_id.clone(),
             pointer_id.clone()
         );
-        let (mut ws_tx, mut ws_rx) = ws.split();
-        // validate token
-        if let Err(err) = Self::verify_token(token, &app_state.jwt_secret) {
-            warn!("verify token error: {:?}", err);
-            if let Err(e) = ws_tx
-                .send(Message::Close(Some(CloseFrame {
-                    code: UNAUTHORIZED_CODE,
-                    reason: Cow::Owned("knock off".to_string()),
-                })))
-                .await
-            {
-                error!("send verify failed to client error: {}", e);
+        // all frames go through the outbound queue, the writer task drains it
+        let (out_tx, mut out_rx) = mpsc::channel(app_state.ws_config.outbound_queue_size);
+        let mut write_task = tokio::spawn(async move {
+            while let Some(msg) = out_rx.recv().await {
+                let is_close = matches!(msg, Message::Close(_));
+                if let Err(e) = ws_tx.send(msg).await {
+                    error!("send message to client error: {}", e);
+                    break;
+                }
+                if is_close {
+                    break;
+                }
             }
-            return;
-        }
-        let shared_tx = Arc::new(RwLock::new(ws_tx));
-        let (notify_sender, mut notify_receiver) = tokio::sync::mpsc::channel(1);
+        });
+
+        let (notify_sender, notify_receiver) = mpsc::channel(1);
         let mut hub = app_state.manager.clone();
+        let unacked = Arc::new(UnackedWindow::new(
+            app_state.ws_config.ack_window,
+            Duration::from_millis(app_state.ws_config.ack_timeout),
+            app_state.ws_config.ack_max_retries,
+        ));
+        let conn_id = nanoid::nanoid!();
+        let last_active = Arc::new(AtomicU64::new(now_millis()));
+        let sync_gate = Arc::new(SyncGate::default());
         let client = Client {
             user_id: user_id.clone(),
-            platform_id: pointer_id.clone(),
-            sender: shared_tx.clone(),
+            device_id: pointer_id.clone(),
+            conn_id: conn_id.clone(),
+            sender: out_tx.clone(),
             platform,
+            codec,
             notify_sender,
+            full_since: AtomicU64::new(0),
+            slow_consumer_timeout: Duration::from_millis(app_state.ws_config.slow_consumer_timeout),
+            unacked: unacked.clone(),
+            last_active: last_active.clone(),
+            sync_gate: sync_gate.clone(),
         };
         hub.register(user_id.clone(), client).await;
 
-        // send ping message to client
-        let cloned_tx = shared_tx.clone();
+        // send ping message to client, and close the half-open connection
+        // which receives nothing in the heartbeat timeout
+        let cloned_tx = out_tx.clone();
+        let cloned_hub = hub.clone();
+        let cloned_user_id = user_id.clone();
+        let cloned_last_active = last_active.clone();
+        let interval = Duration::from_secs(app_state.ws_config.heartbeat_interval.max(1));
+        let timeout = interval * app_state.ws_config.max_missed_heartbeats.max(1);
         let mut ping_task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
             loop {
-                if let Err(e) = cloned_tx
-                    .write()
-                    .await
-                    .send(Message::Ping(Vec::new()))
-                    .await
-                {
+                ticker.tick().await;
+                let idle = now_millis().saturating_sub(cloned_last_active.load(Ordering::Relaxed));
+                if idle > timeout.as_millis() as u64 {
+                    warn!("heartbeat of {} timeout, idle {}ms", cloned_user_id, idle);
+                    break;
+                }
+                // the queue is still full after a whole interval, evict the slow client
+                if let Err(e) = cloned_tx.try_send(Message::Ping(Vec::new())) {
                     error!("send ping error：{:?}", e);
                     // break this task, it will end this conn
                     break;
                 }
-                tokio::time::sleep(Duration::from_secs(HEART_BEAT_INTERVAL)).await;
+                // keep the last seen time fresh while the connection is alive
+                cloned_hub.heartbeat(&cloned_user_id).await;
             }
         });
 
-        let shared_clone = shared_tx.clone();
-        // watch knock off signal
-        let mut watch_task = tokio::spawn(async move {
-            if notify_receiver.recv().await.is_none() {
-                info!("client {} knock off", pointer_id);
-                // send knock off signal to ws server
-                if let Err(e) = shared_clone
-                    .write()
-                    .await
-                    .send(Message::Close(Some(CloseFrame {
-                        code: KNOCK_OFF_CODE,
-                        reason: Cow::Owned("knock off".to_string()),
-                    })))
-                    .await
-                {
-                    error!("send knock off signal to client error: {}", e);
-                }
-            }
-        });
+        let device_id = pointer_id.clone();
+        let mut watch_task = tokio::spawn(Self::watch_knock_off(
+            pointer_id,
+            notify_receiver,
+            out_tx.clone(),
+            codec,
+        ));
+        let mut retry_task = tokio::spawn(Self::retry_unacked(
+            unacked.clone(),
+            out_tx.clone(),
+            sync_gate.clone(),
+        ));
 
         // spawn a new task to receive message
-        let cloned_hub = hub.clone();
-        let shared_tx = shared_tx.clone();
-        // receive message from client
+        let mut inbound = Inbound {
+            hub: hub.clone(),
+            sender: out_tx.clone(),
+            user_id: user_id.clone(),
+            device_id: device_id.clone(),
+            platform,
+            codec,
+            unacked,
+            sync_gate,
+            limiter: InboundLimiter::new(
+                user_id.clone(),
+                hub.cache.clone(),
+                &app_state.ws_config.rate_limit,
+            ),
+        };
+        let cloned_tx = out_tx.clone();
+        // receive message from client, return true if the close frame is queued
         let mut rec_task = tokio::spawn(async move {
             while let Some(Ok(msg)) = ws_rx.next().await {
+                // any frame proves the connection is alive
+                last_active.store(now_millis(), Ordering::Relaxed);
                 // 处理消息
-                match msg {
-                    Message::Text(text) => {
-                        let result = serde_json::from_str(&text);
-                        if result.is_err() {
-                            error!("deserialize error: {:?}； source: {text}", result.err());
+                let msg: Msg = match msg {
+                    Message::Text(text) => match serde_json::from_str(&text) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            error!("deserialize error: {:?}； source: {text}", e);
                             continue;
                         }
-
-                        if cloned_hub.broadcast(result.unwrap()).await.is_err() {
-                            // if broadcast not available, close the connection
-                            break;
+                    },
+                    Message::Binary(b) => match codec.decode(&b) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            error!("deserialize error: {:?}； source: {:?}", e, b);
+                            continue;
                         }
-                    }
+                    },
                     Message::Ping(_) => {
-                        if let Err(e) = shared_tx
-                            .write()
-                            .await
-                            .send(Message::Pong(Vec::new()))
-                            .await
-                        {
-                            error!("reply ping error : {:?}", e);
-                            break;
+                        match cloned_tx.try_send(Message::Pong(Vec::new())) {
+                            // the client will ping again
+                            Ok(()) | Err(TrySendError::Full(_)) => {}
+                            Err(e) => {
+                                error!("reply ping error : {:?}", e);
+                                break;
+                            }
                         }
+                        continue;
                     }
-                    Message::Pong(_) => {
-                        // tracing::debug!("received pong message");
-                    }
+                    Message::Pong(_) => continue,
                     Message::Close(info) => {
                         if let Some(info) = info {
                             warn!("client closed {}", info.reason);
                         }
                         break;
                     }
-                    Message::Binary(b) => {
-                        let result = bincode::deserialize(&b);
-                        if result.is_err() {
-                            error!("deserialize error: {:?}； source: {:?}", result.err(), b);
-                            continue;
-                        }
-                        let msg: Msg = result.unwrap();
-                        // todo need to judge the local id is empty by message type
-                        // if msg.local_id.is_empty() {
-                        //     warn!("receive empty message");
-                        //     continue;
-                        // }
-                        if cloned_hub.broadcast(msg).await.is_err() {
-                            break;
-                        }
-                    }
+                };
+                match inbound.handle(msg).await {
+                    Flow::Continue => {}
+                    Flow::Close => return true,
+                    Flow::Break => break,
                 }
             }
+            false
         });
         let mut need_unregister = true;
         tokio::select! {
-            _ = (&mut ping_task) => {rec_task.abort(); watch_task.abort();},
-            _ = (&mut watch_task) => {need_unregister = false; rec_task.abort(); ping_task.abort();},
-            _ = (&mut rec_task) => {ping_task.abort(); watch_task.abort();},
+            _ = (&mut ping_task) => {},
+            flush = (&mut watch_task) => {
+                need_unregister = false;
+                rec_task.abort();
+                ping_task.abort();
+                retry_task.abort();
+                // wait for the knock off frames to be written
+                if flush.unwrap_or_default() {
+                    let timeout = Duration::from_secs(CLOSE_FLUSH_TIMEOUT);
+                    let _ = tokio::time::timeout(timeout, &mut write_task).await;
+                }
+            },
+            flush = (&mut rec_task) => {
+                // wait for the close frame to be written
+                if flush.unwrap_or_default() {
+                    let timeout = Duration::from_secs(CLOSE_FLUSH_TIMEOUT);
+                    let _ = tokio::time::timeout(timeout, &mut write_task).await;
+                }
+            },
+            _ = (&mut write_task) => {},
+            _ = (&mut retry_task) => {},
         }
+        // one of the tasks is finished, stop the others
+        ping_task.abort();
+        watch_task.abort();
+        rec_task.abort();
+        write_task.abort();
+        retry_task.abort();
 
         // lost the connection, remove the client from hub
         if need_unregister {
-            hub.unregister(user_id, platform).await;
+            hub.unregister(user_id, device_id, conn_id).await;
         }
         tracing::debug!("client thread exit {}", hub.hub.iter().count());
     }
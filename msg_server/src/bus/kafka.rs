@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use tracing::info;
+
+use abi::config::KafkaConfig;
+use abi::errors::Error;
+
+use crate::bus::{BusConsumer, MessageBus, Record};
+use crate::dead_letter::DeadLetter;
+use crate::kafka;
+
+pub struct KafkaBus {
+    producer: FutureProducer,
+    config: KafkaConfig,
+}
+
+impl KafkaBus {
+    pub async fn new(config: &KafkaConfig) -> Self {
+        let producer = kafka::producer(config).expect("Producer creation error");
+
+        kafka::ensure_topic_exists(config, &config.topic)
+            .await
+            .expect("Topic creation error");
+
+        Self {
+            producer,
+            config: config.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for KafkaBus {
+    /// the messages of the same key go to the same partition and keep the order
+    async fn publish(&self, key: &str, payload: String) -> Result<(), Error> {
+        let record: FutureRecord<str, String> = FutureRecord::to(&self.config.topic)
+            .key(key)
+            .payload(&payload);
+        self.producer
+            .send(record, Duration::from_secs(0))
+            .await
+            .map_err(|(e, _)| Error::internal(e))?;
+        Ok(())
+    }
+
+    async fn consumer(&self) -> Result<Box<dyn BusConsumer>, Error> {
+        info!("start kafka consumer:\t{:?}", self.config);
+        // init kafka consumer
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", &self.config.group)
+            .set("bootstrap.servers", self.config.hosts.join(","))
+            .set("enable.auto.commit", "false")
+            .set(
+                "session.timeout.ms",
+                self.config.consumer.session_timeout.to_string(),
+            )
+            .set("socket.timeout.ms", self.config.connect_timeout.to_string())
+            .set("enable.partition.eof", "false")
+            .set(
+                "auto.offset.reset",
+                self.config.consumer.auto_offset_reset.clone(),
+            )
+            .create()
+            .map_err(Error::internal)?;
+
+        // todo register to service register center to monitor the service
+        // subscribe to topic, the partitions are shared by the instances of the group,
+        // and the messages of each partition are handled one by one,
+        // so the messages keyed by the same receiver keep the order
+        consumer
+            .subscribe(&[&self.config.topic])
+            .map_err(Error::internal)?;
+
+        let dead_letter = DeadLetter::new(&self.config)
+            .await
+            .map_err(Error::internal)?;
+
+        Ok(Box::new(KafkaBusConsumer {
+            consumer,
+            dead_letter,
+        }))
+    }
+}
+
+struct KafkaBusConsumer {
+    consumer: StreamConsumer,
+    dead_letter: DeadLetter,
+}
+
+#[async_trait]
+impl BusConsumer for KafkaBusConsumer {
+    async fn recv(&self) -> Result<Record, Error> {
+        let msg = self.consumer.recv().await.map_err(Error::internal)?;
+        Ok(Record {
+            key: msg.key().map(<[u8]>::to_vec),
+            payload: msg.payload().map(<[u8]>::to_vec),
+            topic: msg.topic().to_string(),
+            partition: msg.partition(),
+            offset: msg.offset(),
+        })
+    }
+
+    async fn dead_letter(&self, record: &Record, reason: &str) {
+        self.dead_letter.send(record, reason).await;
+    }
+
+    /// commit the offset after the last record of each partition
+    fn commit(&self, records: &[Record]) -> Result<(), Error> {
+        // the records of a partition are in order
+        let offsets: HashMap<(&str, i32), i64> = records
+            .iter()
+            .map(|record| ((record.topic.as_str(), record.partition), record.offset + 1))
+            .collect();
+        if offsets.is_empty() {
+            return Ok(());
+        }
+        let mut list = TopicPartitionList::with_capacity(offsets.len());
+        for ((topic, partition), offset) in offsets {
+            list.add_partition_offset(topic, partition, Offset::Offset(offset))
+                .map_err(Error::internal)?;
+        }
+        self.consumer
+            .commit(&list, CommitMode::Async)
+            .map_err(Error::internal)
+    }
+}
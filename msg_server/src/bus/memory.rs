@@ -0,0 +1,117 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+use tracing::error;
+
+use abi::errors::Error;
+
+use crate::bus::{BusConsumer, MessageBus, Record};
+
+/// the records are queued in order, like a topic with one partition
+const TOPIC: &str = "memory";
+
+const CHANNEL_SIZE: usize = 1024;
+
+/// the bus for a single process, there is only one consumer,
+/// the messages not consumed are lost when the process exits
+pub struct MemoryBus {
+    sender: mpsc::Sender<Record>,
+    receiver: Mutex<Option<mpsc::Receiver<Record>>>,
+    offset: AtomicI64,
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(CHANNEL_SIZE);
+        Self {
+            sender,
+            receiver: Mutex::new(Some(receiver)),
+            offset: AtomicI64::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for MemoryBus {
+    async fn publish(&self, key: &str, payload: String) -> Result<(), Error> {
+        let record = Record {
+            key: Some(key.as_bytes().to_vec()),
+            payload: Some(payload.into_bytes()),
+            topic: TOPIC.to_string(),
+            partition: 0,
+            offset: self.offset.fetch_add(1, Ordering::Relaxed),
+        };
+        self.sender
+            .send(record)
+            .await
+            .map_err(|_| Error::internal_with_details("message bus is closed"))
+    }
+
+    async fn consumer(&self) -> Result<Box<dyn BusConsumer>, Error> {
+        let receiver = self
+            .receiver
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| Error::internal_with_details("message bus is consumed already"))?;
+        Ok(Box::new(MemoryConsumer {
+            receiver: tokio::sync::Mutex::new(receiver),
+        }))
+    }
+}
+
+struct MemoryConsumer {
+    receiver: tokio::sync::Mutex<mpsc::Receiver<Record>>,
+}
+
+#[async_trait]
+impl BusConsumer for MemoryConsumer {
+    async fn recv(&self) -> Result<Record, Error> {
+        self.receiver
+            .lock()
+            .await
+            .recv()
+            .await
+            .ok_or_else(|| Error::internal_with_details("message bus is closed"))
+    }
+
+    /// there is no storage for the dead letters, they are logged only
+    async fn dead_letter(&self, record: &Record, reason: &str) {
+        error!(
+            "dead letter message {}@{}: {}, payload: {}",
+            record.topic,
+            record.offset,
+            reason,
+            String::from_utf8_lossy(record.payload.as_deref().unwrap_or_default())
+        );
+    }
+
+    fn commit(&self, _records: &[Record]) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_bus_should_work() {
+        let bus = MemoryBus::default();
+        let consumer = bus.consumer().await.unwrap();
+        assert!(bus.consumer().await.is_err());
+
+        bus.publish("a", "1".to_string()).await.unwrap();
+        bus.publish("b", "2".to_string()).await.unwrap();
+
+        let record = consumer.recv().await.unwrap();
+        assert_eq!(record.key.as_deref(), Some("a".as_bytes()));
+        assert_eq!(record.payload.as_deref(), Some("1".as_bytes()));
+        assert_eq!(record.offset, 0);
+        let record = consumer.recv().await.unwrap();
+        assert_eq!(record.payload.as_deref(), Some("2".as_bytes()));
+        assert_eq!(record.offset, 1);
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use abi::config::{Config, MessageBusKind};
+use abi::errors::Error;
+
+#[cfg(feature = "kafka")]
+mod kafka;
+mod memory;
+
+/// the record received from the bus
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    pub key: Option<Vec<u8>>,
+    pub payload: Option<Vec<u8>>,
+    /// the position of the record, used to commit or dead letter it
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// the queue between the chat rpc service and the consumer,
+/// the messages with the same key are received in order
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    async fn publish(&self, key: &str, payload: String) -> Result<(), Error>;
+
+    /// subscribe the messages as the consumer group
+    async fn consumer(&self) -> Result<Box<dyn BusConsumer>, Error>;
+}
+
+#[async_trait]
+pub trait BusConsumer: Send + Sync {
+    /// wait for the next record
+    async fn recv(&self) -> Result<Record, Error>;
+
+    /// keep the record failed to be handled with the reason,
+    /// it is not lost by committing the following records
+    async fn dead_letter(&self, record: &Record, reason: &str);
+
+    /// the committed records are not received again after restarting
+    fn commit(&self, records: &[Record]) -> Result<(), Error>;
+}
+
+pub async fn message_bus(config: &Config) -> Arc<dyn MessageBus> {
+    match config.message_bus {
+        #[cfg(feature = "kafka")]
+        MessageBusKind::Kafka => Arc::new(kafka::KafkaBus::new(&config.kafka).await),
+        #[cfg(not(feature = "kafka"))]
+        MessageBusKind::Kafka => {
+            panic!("the kafka message bus is not built, enable the `kafka` feature or use the memory bus")
+        }
+        MessageBusKind::Memory => Arc::new(memory::MemoryBus::default()),
+    }
+}
@@ -1,16 +1,20 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
-use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
-use rdkafka::{ClientConfig, Message};
-use tracing::{debug, error, info, warn};
+use tokio::time::Instant;
+use tracing::{debug, error, warn};
 
-use abi::config::Config;
+use abi::config::{Config, KafkaConsumer};
 use abi::errors::Error;
-use abi::message::{GroupMemSeq, Msg, MsgRead, MsgType};
+use abi::message::{FriendshipStatus, GroupMemSeq, Msg, MsgRead, MsgType, ReadCursor, ReadReceipt};
 use cache::Cache;
 use db::message::MsgRecBoxRepo;
 use db::{msg_rec_box_repo, DbRepo};
 
+use crate::bus::{BusConsumer, MessageBus, Record};
+use crate::notification::{notification_provider, Notifier};
 use crate::pusher::{push_service, Pusher};
 
 /// message type: single, group, other
@@ -20,44 +24,33 @@ enum MsgType2 {
     Group,
 }
 
+/// the message of a batch to be stored and pushed
+struct Pending {
+    msg: Msg,
+    msg_type: MsgType2,
+    need_increase_seq: bool,
+    need_history: bool,
+    send_to_db: bool,
+    /// the messages with seq are handled idempotently by the server id
+    idempotent: bool,
+    members: Vec<GroupMemSeq>,
+}
+
 pub struct ConsumerService {
-    consumer: StreamConsumer,
+    consumer: Box<dyn BusConsumer>,
     db: Arc<DbRepo>,
     msg_box: Arc<dyn MsgRecBoxRepo>,
     pusher: Arc<dyn Pusher>,
+    /// notify the offline receivers, none if the push notification is disabled
+    notifier: Option<Notifier>,
     cache: Arc<dyn Cache>,
     seq_step: i32,
+    config: KafkaConsumer,
 }
 
 impl ConsumerService {
-    pub async fn new(config: &Config) -> Self {
-        info!("start kafka consumer:\t{:?}", config.kafka);
-        // init kafka consumer
-        let consumer: StreamConsumer = ClientConfig::new()
-            .set("group.id", &config.kafka.group)
-            .set("bootstrap.servers", config.kafka.hosts.join(","))
-            .set("enable.auto.commit", "false")
-            .set(
-                "session.timeout.ms",
-                config.kafka.consumer.session_timeout.to_string(),
-            )
-            .set(
-                "socket.timeout.ms",
-                config.kafka.connect_timeout.to_string(),
-            )
-            .set("enable.partition.eof", "false")
-            .set(
-                "auto.offset.reset",
-                config.kafka.consumer.auto_offset_reset.clone(),
-            )
-            .create()
-            .expect("Consumer creation failed");
-
-        // todo register to service register center to monitor the service
-        // subscribe to topic
-        consumer
-            .subscribe(&[&config.kafka.topic])
-            .expect("Can't subscribe to specified topic");
+    pub async fn new(config: &Config, bus: &dyn MessageBus) -> Self {
+        let consumer = bus.consumer().await.expect("Consumer creation failed");
 
         let pusher = push_service(config).await;
         let db = Arc::new(DbRepo::new(config).await);
@@ -65,117 +58,337 @@ impl ConsumerService {
         let seq_step = config.redis.seq_step;
 
         let cache = cache::cache(config);
-        let msg_box = msg_rec_box_repo(config).await;
+        let msg_box = msg_rec_box_repo(config)
+            .await
+            .expect("message box can't open");
+        let notifier = notification_provider(config)
+            .map(|provider| Notifier::new(provider, cache.clone(), db.clone()));
 
         Self {
             consumer,
             db,
             msg_box,
             pusher,
+            notifier,
             cache,
             seq_step,
+            config: config.kafka.consumer.clone(),
         }
     }
 
     pub async fn consume(&mut self) -> Result<(), Error> {
         loop {
-            match self.consumer.recv().await {
-                Err(e) => error!("Kafka error: {}", e),
-                Ok(m) => {
-                    if let Some(Ok(payload)) = m.payload_view::<str>() {
-                        if let Err(e) = self.handle_msg(payload).await {
+            let records = self.poll().await;
+            let mut batch = Vec::with_capacity(records.len());
+            for record in records.iter() {
+                match Self::decode(record) {
+                    Ok(Some(msg)) => batch.push((record, msg)),
+                    Ok(None) => {}
+                    // the broken message can not be handled by retrying
+                    Err(e) => {
+                        error!("Failed to decode message: {:?}", e);
+                        self.consumer.dead_letter(record, &e.to_string()).await;
+                    }
+                }
+            }
+
+            let msgs = batch.iter().map(|(_, msg)| msg.clone()).collect();
+            if let Err(e) = self.handle_with_retry(msgs).await {
+                if let [(record, _)] = batch.as_slice() {
+                    error!("Failed to handle message: {:?}", e);
+                    self.consumer.dead_letter(record, &e.to_string()).await;
+                } else {
+                    // find out the failed messages by handling them one by one,
+                    // the handled ones are skipped by the idempotency
+                    warn!("Failed to handle {} messages: {:?}", batch.len(), e);
+                    for (record, msg) in batch {
+                        if let Err(e) = self.handle_with_retry(vec![msg]).await {
                             error!("Failed to handle message: {:?}", e);
-                            continue;
-                        }
-                        if let Err(e) = self.consumer.commit_message(&m, CommitMode::Async) {
-                            error!("Failed to commit message: {:?}", e);
+                            self.consumer.dead_letter(record, &e.to_string()).await;
                         }
                     }
                 }
             }
+
+            // the failed messages are committed after they are dead lettered,
+            // so they are not lost by committing the batch
+            if let Err(e) = self.consumer.commit(&records) {
+                error!("Failed to commit message: {:?}", e);
+            }
+        }
+    }
+
+    /// wait for the first record, then collect the records arriving in the batch timeout
+    async fn poll(&self) -> Vec<Record> {
+        let batch_size = self.config.batch_size.max(1);
+        let mut records = Vec::with_capacity(batch_size);
+        let mut deadline = None;
+        while records.len() < batch_size {
+            let result = match deadline {
+                None => self.consumer.recv().await,
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.consumer.recv()).await {
+                        Ok(result) => result,
+                        Err(_) => break,
+                    }
+                }
+            };
+            match result {
+                Ok(record) => {
+                    deadline.get_or_insert_with(|| {
+                        Instant::now() + Duration::from_millis(self.config.batch_timeout)
+                    });
+                    records.push(record);
+                }
+                Err(e) => error!("Message bus error: {:?}", e),
+            }
         }
+        records
     }
 
-    // todo handle error for the task
-    async fn handle_msg(&self, payload: &str) -> Result<(), Error> {
+    fn decode(record: &Record) -> Result<Option<Msg>, Error> {
+        let Some(payload) = &record.payload else {
+            return Ok(None);
+        };
+        let payload = std::str::from_utf8(payload).map_err(Error::internal)?;
         debug!("Received message: {:#?}", payload);
 
-        let mut msg: Msg = serde_json::from_str(payload)?;
+        let msg: Msg = serde_json::from_str(payload)?;
+        MsgType::try_from(msg.msg_type).map_err(Error::internal)?;
+        Ok(Some(msg))
+    }
+
+    async fn handle_with_retry(&self, msgs: Vec<Msg>) -> Result<(), Error> {
+        retry(&self.config, || self.handle_batch(msgs.clone())).await
+    }
 
-        let mt = MsgType::try_from(msg.msg_type).map_err(Error::internal)?;
+    async fn handle_batch(&self, msgs: Vec<Msg>) -> Result<(), Error> {
+        let mut pending = Vec::with_capacity(msgs.len());
+        for msg in msgs {
+            let mt = MsgType::try_from(msg.msg_type).map_err(Error::internal)?;
+
+            // the read messages may refer to the messages before them in the batch,
+            // so the messages before them are handled first to keep the order of the batch.
+            // presence is pushed to the friends, not stored
+            if mt == MsgType::Read || mt == MsgType::Presence {
+                self.handle_pending(std::mem::take(&mut pending)).await?;
+                if mt == MsgType::Read {
+                    self.handle_msg_read(msg).await?;
+                } else {
+                    self.handle_presence(msg).await?;
+                }
+                continue;
+            }
 
-        // handle message read type
-        if mt == MsgType::Read {
-            return self.handle_msg_read(msg).await;
+            let (msg_type, need_increase_seq, need_history) = Self::classify_msg_type(mt);
+            let idempotent =
+                !msg.server_id.is_empty() && (need_increase_seq || msg_type == MsgType2::Group);
+            pending.push(Pending {
+                msg,
+                msg_type,
+                need_increase_seq,
+                need_history,
+                send_to_db: Self::get_send_to_db_flag(&mt),
+                idempotent,
+                members: vec![],
+            });
         }
+        self.handle_pending(pending).await
+    }
 
-        let (msg_type, need_increase_seq, need_history) = self.classify_msg_type(mt).await;
+    /// store and push the messages in batch
+    async fn handle_pending(&self, mut pending: Vec<Pending>) -> Result<(), Error> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+        self.assign_seq(&mut pending).await?;
+        self.store(&pending).await?;
+        let pushed = self.push(pending).await?;
+        self.notify(&pushed).await;
+        Ok(())
+    }
 
+    /// the seqs of the single messages are increased in one round trip,
+    /// the messages of the same receiver get the seqs in the order of the batch.
+    /// the redelivered messages keep the seqs assigned by the previous delivery
+    async fn assign_seq(&self, pending: &mut [Pending]) -> Result<(), Error> {
         // check send seq if need to increase max_seq
-        self.handle_send_seq(&msg.send_id).await?;
-
-        // handle receiver seq
-        if need_increase_seq {
-            let cur_seq = self.increase_message_seq(&msg.receiver_id).await?;
-            msg.seq = cur_seq;
+        let senders: HashSet<&str> = pending.iter().map(|p| p.msg.send_id.as_str()).collect();
+        for sender in senders {
+            self.handle_send_seq(sender).await?;
         }
 
-        // query members id from cache if the message type is group
-        let members = self.handle_group_seq(&msg_type, &mut msg).await?;
-
-        let mut tasks = Vec::with_capacity(2);
-        // send to db
-        if Self::get_send_to_db_flag(&mt) {
-            let cloned_msg = msg.clone();
-            let cloned_type = msg_type.clone();
-            let cloned_members = members.clone();
-            // send to db rpc server
-            let db = self.db.clone();
-            let msg_box = self.msg_box.clone();
-            let to_db = tokio::spawn(async move {
-                if let Err(e) = Self::send_to_db(
-                    db,
-                    msg_box,
-                    cloned_msg,
-                    cloned_type,
-                    need_history,
-                    cloned_members,
-                )
-                .await
-                {
-                    error!("failed to send message to db, error: {:?}", e);
+        let server_ids: Vec<String> = pending
+            .iter()
+            .filter(|p| p.idempotent)
+            .map(|p| p.msg.server_id.clone())
+            .collect();
+        let mut assigned = self.cache.query_msg_seq(&server_ids).await?.into_iter();
+
+        // the seqs to be remembered, (server id, seqs)
+        let mut new_seqs = Vec::new();
+        // the single messages need to increase seq, (index, receiver id)
+        let mut increasing = Vec::new();
+        for (index, p) in pending.iter_mut().enumerate() {
+            let seqs = if p.idempotent {
+                assigned.next().unwrap_or_default()
+            } else {
+                vec![]
+            };
+            if p.need_increase_seq {
+                match seqs.first() {
+                    Some(seq) => p.msg.seq = seq.cur_seq,
+                    None => increasing.push((index, p.msg.receiver_id.clone())),
                 }
-            });
+            } else if p.msg_type == MsgType2::Group {
+                // the members may be changed by the previous message of the group
+                let is_new = seqs.is_empty();
+                p.members = self.handle_group_seq(&p.msg, seqs).await?;
+                if p.idempotent && is_new {
+                    new_seqs.push((p.msg.server_id.clone(), p.members.clone()));
+                }
+            }
+        }
 
-            tasks.push(to_db);
+        let receivers: Vec<String> = increasing.iter().map(|(_, id)| id.clone()).collect();
+        let seqs = self.cache.increase_seq_batch(&receivers).await?;
+        let mut need_update = HashSet::new();
+        for ((index, receiver), (cur_seq, _, updated)) in increasing.into_iter().zip(seqs) {
+            let p = &mut pending[index];
+            p.msg.seq = cur_seq;
+            if p.idempotent {
+                let seq = GroupMemSeq::new(receiver.clone(), cur_seq, 0, false);
+                new_seqs.push((p.msg.server_id.clone(), vec![seq]));
+            }
+            if updated {
+                need_update.insert(receiver);
+            }
         }
 
-        // send to pusher
-        let pusher = self.pusher.clone();
-        let to_pusher = tokio::spawn(async move {
-            match msg_type {
-                MsgType2::Single => {
-                    if let Err(e) = pusher.push_single_msg(msg).await {
-                        error!("failed to send message to pusher, error: {:?}", e);
-                    }
-                }
-                MsgType2::Group => {
-                    if let Err(e) = pusher.push_group_msg(msg, members).await {
-                        error!("failed to send message to pusher, error: {:?}", e);
+        // the max seqs are saved before the seqs are remembered for the redelivery
+        let need_update: Vec<String> = need_update.into_iter().collect();
+        self.db.seq.save_max_seq_batch(&need_update).await?;
+        self.cache.save_msg_seq(&new_seqs).await
+    }
+
+    /// save the batch into the receive box and the history, one write for each
+    async fn store(&self, pending: &[Pending]) -> Result<(), Error> {
+        let mut inbox = Vec::with_capacity(pending.len());
+        let mut history = Vec::with_capacity(pending.len());
+        let mut received = Vec::new();
+        for p in pending.iter().filter(|p| p.send_to_db) {
+            // if the message type is friendship/group-operation delivery, we should delete it from mongodb
+            if p.msg.msg_type == MsgType::GroupDismissOrExitReceived as i32
+                || p.msg.msg_type == MsgType::GroupInvitationReceived as i32
+                || p.msg.msg_type == MsgType::FriendshipReceived as i32
+            {
+                received.push(p.msg.server_id.as_str());
+                continue;
+            }
+            if p.need_history {
+                history.push(p.msg.clone());
+            }
+            inbox.push((p.msg.clone(), p.members.clone()));
+        }
+
+        tokio::try_join!(
+            async {
+                self.msg_box
+                    .save_messages(inbox)
+                    .await
+                    .inspect_err(|e| error!("save messages to mongodb failed: {}", e))
+            },
+            async {
+                self.db
+                    .msg
+                    .save_messages(&history)
+                    .await
+                    .inspect_err(|e| error!("save messages to db failed: {}", e))
+            },
+        )?;
+
+        for server_id in received {
+            self.msg_box
+                .delete_message(server_id)
+                .await
+                .inspect_err(|e| error!("delete message from mongodb failed: {}", e))?;
+        }
+        Ok(())
+    }
+
+    /// the messages to the same receiver are pushed one by one to keep the order,
+    /// the receivers are pushed concurrently
+    /// return the chat messages pushed with their receivers for the notification
+    async fn push(&self, pending: Vec<Pending>) -> Result<Vec<(Msg, Vec<String>)>, Error> {
+        let mut receivers: HashMap<String, Vec<Pending>> = HashMap::new();
+        for p in pending {
+            receivers
+                .entry(p.msg.receiver_id.clone())
+                .or_default()
+                .push(p);
+        }
+        let pushed =
+            futures::future::try_join_all(receivers.into_values().map(|list| async move {
+                let mut pushed = Vec::new();
+                for p in list {
+                    if let Some(msg) = self.push_msg(p).await? {
+                        pushed.push(msg);
                     }
                 }
-            }
-        });
-        tasks.push(to_pusher);
+                Ok::<_, Error>(pushed)
+            }))
+            .await?;
+        Ok(pushed.into_iter().flatten().collect())
+    }
 
-        futures::future::try_join_all(tasks)
-            .await
-            .map_err(Error::internal)?;
+    /// the message is stored already, the receivers get it by syncing if the push fails
+    async fn push_msg(&self, p: Pending) -> Result<Option<(Msg, Vec<String>)>, Error> {
+        // push at most once, the receivers sync the message pushed by the previous delivery
+        if p.idempotent && !self.cache.mark_msg_pushed(&p.msg.server_id).await? {
+            debug!("message {} is pushed already", p.msg.server_id);
+            return Ok(None);
+        }
+        let notification = self.notification_receivers(&p);
+        let result = match p.msg_type {
+            MsgType2::Single => self.pusher.push_single_msg(p.msg).await,
+            MsgType2::Group => self.pusher.push_group_msg(p.msg, p.members).await,
+        };
+        if let Err(e) = result {
+            error!("failed to send message to pusher, error: {:?}", e);
+        }
+        Ok(notification)
+    }
 
-        Ok(())
+    /// only the chat messages are notified to the offline receivers
+    fn notification_receivers(&self, p: &Pending) -> Option<(Msg, Vec<String>)> {
+        self.notifier.as_ref()?;
+        let receivers = if p.msg.msg_type == MsgType::SingleMsg as i32 {
+            vec![p.msg.receiver_id.clone()]
+        } else if p.msg.msg_type == MsgType::GroupMsg as i32 {
+            p.members.iter().map(|m| m.mem_id.clone()).collect()
+        } else {
+            return None;
+        };
+        Some((p.msg.clone(), receivers))
+    }
+
+    /// the notification is best effort, the messages are stored already
+    async fn notify(&self, pushed: &[(Msg, Vec<String>)]) {
+        let Some(notifier) = &self.notifier else {
+            return;
+        };
+        if pushed.is_empty() {
+            return;
+        }
+        if let Err(e) = notifier.notify(pushed).await {
+            error!("failed to notify offline receivers, error: {:?}", e);
+        }
     }
 
-    async fn classify_msg_type(&self, mt: MsgType) -> (MsgType2, bool, bool) {
+    /// return the type, whether to increase the seq and whether to save the history.
+    /// the chat messages, the call results and the friendship changes are kept in the history,
+    /// the group operations, the signalling and the control messages are not
+    fn classify_msg_type(mt: MsgType) -> (MsgType2, bool, bool) {
         let msg_type;
         let mut need_increase_seq = false;
         let mut need_history = true;
@@ -221,7 +434,18 @@ impl ConsumerService {
             | MsgType::MsgRecResp
             | MsgType::Notification
             | MsgType::Service
-            | MsgType::FriendshipReceived => {
+            | MsgType::FriendshipReceived
+            | MsgType::KnockOff
+            | MsgType::Ack
+            | MsgType::Delivered
+            | MsgType::Signal
+            | MsgType::Presence
+            | MsgType::Heartbeat
+            | MsgType::Sync
+            | MsgType::SyncComplete
+            | MsgType::ReadCursor
+            | MsgType::ReadReceipt
+            | MsgType::GroupReadReceipt => {
                 msg_type = MsgType2::Single;
                 need_history = false;
             }
@@ -256,38 +480,167 @@ impl ConsumerService {
         Ok(())
     }
 
-    async fn increase_message_seq(&self, user_id: &str) -> Result<i64, Error> {
-        let (cur_seq, _, updated) = self.cache.increase_seq(user_id).await?;
-        if updated {
-            self.db.seq.save_max_seq(user_id).await?;
-        }
-        Ok(cur_seq)
-    }
-
     async fn handle_msg_read(&self, msg: Msg) -> Result<(), Error> {
         let data: MsgRead = bincode::deserialize(&msg.content)?;
+        // the sender is verified by the gateway, the user id in the content is not
+        let reader = &msg.send_id;
 
-        self.msg_box.msg_read(&data.user_id, &data.msg_seq).await?;
-        Ok(())
+        let messages = self
+            .msg_box
+            .get_messages_by_seq(reader, &data.msg_seq)
+            .await?;
+
+        // the group messages are ordered by the send time among the members
+        let last_send_time = messages
+            .iter()
+            .map(|m| m.send_time)
+            .max()
+            .unwrap_or_default();
+        self.move_read_cursor(&msg, &data, last_send_time).await?;
+
+        // the receipts are sent for the messages read at the first time,
+        // the messages are marked read after the receipts are saved,
+        // so the redelivery sends the same receipts again
+        let unread = messages.into_iter().filter(|m| !m.is_read).collect();
+        self.send_read_receipts(reader, unread).await?;
+        self.msg_box.msg_read(reader, &data.msg_seq).await
     }
 
-    async fn handle_group_seq(
+    async fn move_read_cursor(
         &self,
-        msg_type: &MsgType2,
-        msg: &mut Msg,
-    ) -> Result<Vec<GroupMemSeq>, Error> {
-        if *msg_type != MsgType2::Group {
-            return Ok(vec![]);
+        msg: &Msg,
+        data: &MsgRead,
+        last_send_time: i64,
+    ) -> Result<(), Error> {
+        // the legacy clients do not tell the conversation
+        let Some(&seq) = data.msg_seq.iter().max() else {
+            return Ok(());
+        };
+        if data.conversation_id.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().timestamp_millis();
+        let cursor = ReadCursor {
+            user_id: msg.send_id.clone(),
+            conversation_id: data.conversation_id.clone(),
+            seq,
+            update_time: now,
+            last_send_time,
+        };
+        if !self.msg_box.save_read_cursor(&cursor).await? {
+            return Ok(());
         }
-        // query group members id from the cache
-        let mut members = self.get_members_id(&msg.receiver_id).await?;
 
-        // retain the members id
-        members.retain(|id| id != &msg.send_id);
+        // tell the other devices of the reader to update the unread count
+        let cursor_msg = Msg {
+            send_id: msg.send_id.clone(),
+            receiver_id: msg.send_id.clone(),
+            device_id: msg.device_id.clone(),
+            send_time: now,
+            msg_type: MsgType::ReadCursor as i32,
+            content: bincode::serialize(&cursor)?,
+            ..Default::default()
+        };
+        self.pusher.push_single_msg(cursor_msg).await
+    }
 
-        // increase the members seq
-        let seq = self.cache.incr_group_seq(members).await?;
+    /// tell the senders that their messages are read,
+    /// the receipts of single chat are saved into the receive box of the senders like other messages,
+    /// so the offline senders get them by pulling or syncing.
+    /// the receipts of group are pushed only, the read status is queried by the api.
+    /// the server id of the receipt is derived from the reader and the read message,
+    /// so the redelivered receipts are handled idempotently like the other messages
+    async fn send_read_receipts(&self, reader: &str, unread: Vec<Msg>) -> Result<(), Error> {
+        if unread.is_empty() || self.cache.is_read_receipt_disabled(reader).await? {
+            return Ok(());
+        }
+
+        let now = chrono::Utc::now().timestamp_millis();
+        // (sender, group id) -> receipt, the group id is empty for single chat
+        let mut receipts: HashMap<(String, String), ReadReceipt> = HashMap::new();
+        for m in unread {
+            let is_group = m.msg_type == MsgType::GroupMsg as i32 && !m.group_id.is_empty();
+            let is_single = m.msg_type == MsgType::SingleMsg as i32 && m.group_id.is_empty();
+            if !is_group && !is_single {
+                continue;
+            }
+            let receipt = receipts
+                .entry((m.send_id, m.group_id))
+                .or_insert_with(|| ReadReceipt {
+                    user_id: reader.to_string(),
+                    read_time: now,
+                    ..Default::default()
+                });
+            receipt.server_ids.push(m.server_id);
+            receipt.send_seq = receipt.send_seq.max(m.send_seq);
+        }
+
+        let mut pending = Vec::with_capacity(receipts.len());
+        for ((sender, group_id), mut receipt) in receipts {
+            // a message is read by the reader only once, so it is in one receipt only
+            receipt.server_ids.sort();
+            let server_id = receipt_server_id(reader, &receipt.server_ids[0]);
+            let is_single = group_id.is_empty();
+            let msg_type = if is_single {
+                MsgType::ReadReceipt
+            } else {
+                MsgType::GroupReadReceipt
+            };
+            let msg = Msg {
+                send_id: reader.to_string(),
+                receiver_id: sender,
+                group_id,
+                server_id,
+                send_time: now,
+                msg_type: msg_type as i32,
+                content: bincode::serialize(&receipt)?,
+                ..Default::default()
+            };
+            pending.push(Pending {
+                msg,
+                msg_type: MsgType2::Single,
+                need_increase_seq: is_single,
+                need_history: false,
+                send_to_db: is_single,
+                idempotent: true,
+                members: vec![],
+            });
+        }
+
+        self.assign_seq(&mut pending).await?;
+        self.store(&pending).await?;
+        self.push(pending).await?;
+        Ok(())
+    }
+
+    /// push the presence of the user to the friends,
+    /// the pusher ignores the friends who are offline
+    async fn handle_presence(&self, msg: Msg) -> Result<(), Error> {
+        let friends = self
+            .db
+            .friend
+            .get_friend_list(&msg.send_id, 0)
+            .await?
+            .into_iter()
+            .filter(|friend| friend.status == FriendshipStatus::Accepted as i32)
+            .map(|friend| GroupMemSeq {
+                mem_id: friend.friend_id,
+                ..Default::default()
+            })
+            .collect();
+        self.pusher.push_group_msg(msg, friends).await
+    }
 
+    async fn handle_group_seq(
+        &self,
+        msg: &Msg,
+        assigned: Vec<GroupMemSeq>,
+    ) -> Result<Vec<GroupMemSeq>, Error> {
+        let seq = if assigned.is_empty() {
+            self.increase_group_seq(msg).await?
+        } else {
+            assigned
+        };
         // we should send the whole list to db module and db module will handle the data
 
         // judge the message type;
@@ -311,6 +664,28 @@ impl ConsumerService {
         Ok(seq)
     }
 
+    /// increase the seq of the members except the sender,
+    /// the max seqs are saved before returning
+    async fn increase_group_seq(&self, msg: &Msg) -> Result<Vec<GroupMemSeq>, Error> {
+        // query group members id from the cache
+        let mut members = self.get_members_id(&msg.receiver_id).await?;
+
+        // retain the members id
+        members.retain(|id| id != &msg.send_id);
+
+        // increase the members seq
+        let seq = self.cache.incr_group_seq(members).await?;
+
+        // update the user's seq in postgres
+        let need_update = seq
+            .iter()
+            .filter(|item| item.need_update)
+            .map(|item| item.mem_id.clone())
+            .collect::<Vec<String>>();
+        self.db.seq.save_max_seq_batch(&need_update).await?;
+        Ok(seq)
+    }
+
     /// there is no need to send to db
     /// if the message type is related to call protocol
     #[inline]
@@ -322,30 +697,10 @@ impl ConsumerService {
                 | MsgType::Candidate
                 | MsgType::SingleCallOffer
                 | MsgType::SingleCallInvite
+                | MsgType::Delivered
         )
     }
 
-    async fn send_to_db(
-        db: Arc<DbRepo>,
-        msg_box: Arc<dyn MsgRecBoxRepo>,
-        msg: Msg,
-        msg_type: MsgType2,
-        need_to_history: bool,
-        members: Vec<GroupMemSeq>,
-    ) -> Result<(), Error> {
-        // match the message type to procedure the different method
-        match msg_type {
-            MsgType2::Single => {
-                Self::handle_message(db, msg_box, msg, need_to_history).await?;
-            }
-            MsgType2::Group => {
-                Self::handle_group_message(db, msg_box, msg, need_to_history, members).await?;
-            }
-        }
-
-        Ok(())
-    }
-
     /// query members id from database
     /// and set it to cache
     async fn query_group_members_id_from_db(&self, group_id: &str) -> Result<Vec<String>, Error> {
@@ -362,111 +717,148 @@ impl ConsumerService {
 
         Ok(members_id)
     }
+}
 
-    async fn handle_message(
-        db: Arc<DbRepo>,
-        msg_box: Arc<dyn MsgRecBoxRepo>,
-        message: Msg,
-        need_to_history: bool,
-    ) -> Result<(), Error> {
-        // task 1 save message to postgres
-
-        let mut tasks = Vec::with_capacity(2);
-        if !need_to_history {
-            let cloned_msg = message.clone();
-            let db_task = tokio::spawn(async move {
-                if let Err(e) = db.msg.save_message(cloned_msg).await {
-                    tracing::error!("save message to db failed: {}", e);
-                }
-            });
-            tasks.push(db_task);
+/// retry the retryable errors with exponential backoff,
+/// return the permanent error or the last error if the retries are exhausted
+async fn retry<F, Fut>(config: &KafkaConsumer, mut handle: F) -> Result<(), Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), Error>>,
+{
+    let mut backoff = config.retry_backoff;
+    let mut retries = 0;
+    loop {
+        match handle().await {
+            Err(e) if e.is_retryable() && retries < config.max_retries => {
+                retries += 1;
+                warn!(
+                    "Failed to handle message: {:?}, retry {} in {}ms",
+                    e, retries, backoff
+                );
+                tokio::time::sleep(Duration::from_millis(backoff)).await;
+                backoff = next_backoff(backoff, config.max_retry_backoff);
+            }
+            result => return result,
         }
+    }
+}
 
-        // task 2 save message to mongodb
-        let msg_rec_box_task = tokio::spawn(async move {
-            // if the message type is friendship/group-operation delivery, we should delete it from mongodb
-            if message.msg_type == MsgType::GroupDismissOrExitReceived as i32
-                || message.msg_type == MsgType::GroupInvitationReceived as i32
-                || message.msg_type == MsgType::FriendshipReceived as i32
-            {
-                if let Err(e) = msg_box.delete_message(&message.server_id).await {
-                    tracing::error!("delete message from mongodb failed: {}", e);
-                }
-                return;
-            }
-            if let Err(e) = msg_box.save_message(&message).await {
-                tracing::error!("save message to mongodb failed: {}", e);
-            }
-        });
-        tasks.push(msg_rec_box_task);
+#[inline]
+fn next_backoff(backoff: u64, max_backoff: u64) -> u64 {
+    backoff.saturating_mul(2).min(max_backoff)
+}
 
-        // wait all tasks
-        futures::future::try_join_all(tasks)
-            .await
-            .map_err(Error::internal)?;
-        Ok(())
-    }
+/// the receipt of the reader for the read message
+#[inline]
+fn receipt_server_id(reader: &str, server_id: &str) -> String {
+    format!("receipt:{}:{}", reader, server_id)
+}
 
-    async fn handle_group_message(
-        db: Arc<DbRepo>,
-        msg_box: Arc<dyn MsgRecBoxRepo>,
-        message: Msg,
-        need_to_history: bool,
-        members: Vec<GroupMemSeq>,
-    ) -> Result<(), Error> {
-        // task 1 save message to postgres
-        // update the user's seq in postgres
-        let need_update = members
-            .iter()
-            .enumerate()
-            .filter_map(|(index, item)| {
-                if item.need_update {
-                    members.get(index).map(|v| v.mem_id.clone())
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<String>>();
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
 
-        let cloned_msg = if need_to_history {
-            Some(message.clone())
-        } else {
-            None
-        };
+    use abi::errors::ErrorKind;
 
-        let db_task = tokio::spawn(async move {
-            if !need_update.is_empty() {
-                if let Err(err) = db.seq.save_max_seq_batch(&need_update).await {
-                    tracing::error!("save max seq batch failed: {}", err);
-                    return Err(err);
-                };
-            }
+    use super::*;
 
-            if let Some(cloned_msg) = cloned_msg {
-                if let Err(e) = db.msg.save_message(cloned_msg).await {
-                    tracing::error!("save message to db failed: {}", e);
-                    return Err(e);
-                }
-            }
-            Ok(())
-        });
-
-        // task 2 save message to mongodb
-        let msg_rec_box_task = tokio::spawn(async move {
-            if let Err(e) = msg_box.save_group_msg(message, members).await {
-                tracing::error!("save message to mongodb failed: {}", e);
-                return Err(e);
-            }
-            Ok(())
-        });
+    fn config(max_retries: u32) -> KafkaConsumer {
+        KafkaConsumer {
+            session_timeout: 20000,
+            auto_offset_reset: "earliest".to_string(),
+            max_retries,
+            retry_backoff: 1,
+            max_retry_backoff: 4,
+            batch_size: 100,
+            batch_timeout: 50,
+        }
+    }
 
-        // wait all tasks complete
-        let (db_result, msg_rec_box_result) =
-            tokio::try_join!(db_task, msg_rec_box_task).map_err(Error::internal)?;
+    #[test]
+    fn classify_msg_type_should_decide_history() {
+        let history = [
+            MsgType::SingleMsg,
+            MsgType::SingleCallInviteNotAnswer,
+            MsgType::SingleCallInviteCancel,
+            MsgType::Hangup,
+            MsgType::ConnectSingleCall,
+            MsgType::RejectSingleCall,
+            MsgType::FriendApplyReq,
+            MsgType::FriendApplyResp,
+            MsgType::FriendDelete,
+            MsgType::GroupMsg,
+        ];
+        let no_history = [
+            MsgType::GroupInvitation,
+            MsgType::GroupMemberExit,
+            MsgType::GroupDismiss,
+            MsgType::SingleCallInvite,
+            MsgType::SingleCallOffer,
+            MsgType::Candidate,
+            MsgType::FriendshipReceived,
+            MsgType::Delivered,
+            MsgType::ReadReceipt,
+        ];
+        for mt in history {
+            assert!(ConsumerService::classify_msg_type(mt).2, "{:?}", mt);
+        }
+        for mt in no_history {
+            assert!(!ConsumerService::classify_msg_type(mt).2, "{:?}", mt);
+        }
+    }
 
-        db_result?;
-        msg_rec_box_result?;
+    #[test]
+    fn next_backoff_should_be_capped() {
+        let mut backoff = 200;
+        let mut list = Vec::new();
+        for _ in 0..8 {
+            backoff = next_backoff(backoff, 10000);
+            list.push(backoff);
+        }
+        assert_eq!(list, [400, 800, 1600, 3200, 6400, 10000, 10000, 10000]);
+        assert_eq!(next_backoff(u64::MAX, 10000), 10000);
+    }
 
-        Ok(())
+    #[tokio::test]
+    async fn retry_should_stop_after_max_retries() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&config(3), || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::with_details(
+                ErrorKind::RedisError,
+                "connection lost",
+            ))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn retry_should_not_retry_permanent_error() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&config(3), || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(Error::bad_request("broken message"))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_should_return_once_recovered() {
+        let calls = AtomicU32::new(0);
+        let result = retry(&config(3), || async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(Error::with_details(ErrorKind::DbError, "timeout"))
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
     }
 }
@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::message::{BorrowedMessage, Header, Headers, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message};
+use tracing::{error, info};
+
+use abi::config::KafkaConfig;
+use abi::errors::Error;
+
+use crate::bus::Record;
+use crate::kafka;
+
+/// the headers of the dead lettered messages
+pub const REASON_HEADER: &str = "dlq.reason";
+pub const TOPIC_HEADER: &str = "dlq.topic";
+pub const PARTITION_HEADER: &str = "dlq.partition";
+pub const OFFSET_HEADER: &str = "dlq.offset";
+/// milliseconds timestamp
+pub const FAILED_AT_HEADER: &str = "dlq.failed_at";
+
+/// seconds, stop reading the dead letter topic if there is no message in it
+const IDLE_TIMEOUT: u64 = 10;
+
+/// a message in the dead letter topic with the headers
+#[derive(Debug, Clone)]
+pub struct DeadLetterRecord {
+    /// where the message was consumed from
+    pub topic: String,
+    pub partition: String,
+    pub offset: String,
+    /// milliseconds timestamp
+    pub failed_at: String,
+    pub reason: String,
+    pub payload: Vec<u8>,
+}
+
+/// keep the messages failed to be handled with the reason,
+/// they can be inspected and replayed by the `dlq` command
+pub struct DeadLetter {
+    producer: FutureProducer,
+    topic: String,
+    backoff: Duration,
+}
+
+impl DeadLetter {
+    pub async fn new(config: &KafkaConfig) -> Result<Self, KafkaError> {
+        let producer = kafka::producer(config)?;
+        kafka::ensure_topic_exists(config, &config.dead_letter_topic).await?;
+        Ok(Self {
+            producer,
+            topic: config.dead_letter_topic.clone(),
+            backoff: Duration::from_millis(config.consumer.max_retry_backoff),
+        })
+    }
+
+    /// keep trying until the message is in the dead letter topic,
+    /// the offset of the message can not be committed before that
+    pub async fn send(&self, record: &Record, reason: &str) {
+        let partition = record.partition.to_string();
+        let offset = record.offset.to_string();
+        let failed_at = chrono::Utc::now().timestamp_millis().to_string();
+        let headers = OwnedHeaders::new()
+            .insert(Header {
+                key: REASON_HEADER,
+                value: Some(reason),
+            })
+            .insert(Header {
+                key: TOPIC_HEADER,
+                value: Some(record.topic.as_str()),
+            })
+            .insert(Header {
+                key: PARTITION_HEADER,
+                value: Some(partition.as_str()),
+            })
+            .insert(Header {
+                key: OFFSET_HEADER,
+                value: Some(offset.as_str()),
+            })
+            .insert(Header {
+                key: FAILED_AT_HEADER,
+                value: Some(failed_at.as_str()),
+            });
+
+        loop {
+            let mut dead_letter: FutureRecord<[u8], [u8]> =
+                FutureRecord::to(&self.topic).headers(headers.clone());
+            if let Some(key) = &record.key {
+                dead_letter = dead_letter.key(key);
+            }
+            if let Some(payload) = &record.payload {
+                dead_letter = dead_letter.payload(payload);
+            }
+            match self
+                .producer
+                .send(dead_letter, Duration::from_secs(0))
+                .await
+            {
+                Ok(_) => {
+                    info!(
+                        "dead letter message {}:{}@{}: {}",
+                        record.topic, partition, offset, reason
+                    );
+                    return;
+                }
+                Err((e, _)) => {
+                    error!("send message to dead letter topic error: {:?}", e);
+                    tokio::time::sleep(self.backoff).await;
+                }
+            }
+        }
+    }
+}
+
+/// the dead lettered messages which are not replayed
+pub async fn inspect(config: &KafkaConfig, limit: usize) -> Result<Vec<DeadLetterRecord>, Error> {
+    let consumer = dead_letter_consumer(config)?;
+    let mut records = Vec::new();
+    for _ in 0..limit {
+        let Some(msg) = next(&consumer).await? else {
+            break;
+        };
+        records.push(DeadLetterRecord {
+            topic: header(&msg, TOPIC_HEADER).into_owned(),
+            partition: header(&msg, PARTITION_HEADER).into_owned(),
+            offset: header(&msg, OFFSET_HEADER).into_owned(),
+            failed_at: header(&msg, FAILED_AT_HEADER).into_owned(),
+            reason: header(&msg, REASON_HEADER).into_owned(),
+            payload: msg.payload().unwrap_or_default().to_vec(),
+        });
+    }
+    Ok(records)
+}
+
+/// send the dead lettered messages back to the chat topic,
+/// the replayed messages are committed and will not be inspected or replayed again,
+/// return the count of the replayed messages
+pub async fn replay(config: &KafkaConfig, limit: usize) -> Result<usize, Error> {
+    let consumer = dead_letter_consumer(config)?;
+    let producer = kafka::producer(config).map_err(Error::internal)?;
+    let mut count = 0;
+    for _ in 0..limit {
+        let Some(msg) = next(&consumer).await? else {
+            break;
+        };
+        let mut record: FutureRecord<[u8], [u8]> = FutureRecord::to(&config.topic);
+        if let Some(key) = msg.key() {
+            record = record.key(key);
+        }
+        if let Some(payload) = msg.payload() {
+            record = record.payload(payload);
+        }
+        producer
+            .send(record, Duration::from_secs(0))
+            .await
+            .map_err(|(e, _)| Error::internal(e))?;
+        consumer
+            .commit_message(&msg, CommitMode::Sync)
+            .map_err(Error::internal)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// the consumer group records the progress of replaying
+fn dead_letter_consumer(config: &KafkaConfig) -> Result<StreamConsumer, Error> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", format!("{}-dlq", config.group))
+        .set("bootstrap.servers", config.hosts.join(","))
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set("socket.timeout.ms", config.connect_timeout.to_string())
+        .create()
+        .map_err(Error::internal)?;
+    consumer
+        .subscribe(&[&config.dead_letter_topic])
+        .map_err(Error::internal)?;
+    Ok(consumer)
+}
+
+/// none if there is no more message
+async fn next(consumer: &StreamConsumer) -> Result<Option<BorrowedMessage<'_>>, Error> {
+    match tokio::time::timeout(Duration::from_secs(IDLE_TIMEOUT), consumer.recv()).await {
+        Ok(msg) => msg.map(Some).map_err(Error::internal),
+        Err(_) => Ok(None),
+    }
+}
+
+fn header<'a>(msg: &'a BorrowedMessage<'_>, key: &str) -> Cow<'a, str> {
+    msg.headers()
+        .and_then(|headers| headers.iter().find(|header| header.key == key))
+        .and_then(|header| header.value)
+        .map(String::from_utf8_lossy)
+        .unwrap_or_default()
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::FutureProducer;
+use rdkafka::ClientConfig;
+use tracing::{info, warn};
+
+use abi::config::KafkaConfig;
+
+pub(crate) fn producer(config: &KafkaConfig) -> Result<FutureProducer, KafkaError> {
+    ClientConfig::new()
+        .set("bootstrap.servers", config.hosts.join(","))
+        .set("message.timeout.ms", config.producer.timeout.to_string())
+        .set("socket.timeout.ms", config.connect_timeout.to_string())
+        .set("acks", config.producer.acks.clone())
+        // make sure the message is sent exactly once
+        .set("enable.idempotence", "true")
+        .set("retries", config.producer.max_retry.to_string())
+        .set(
+            "retry.backoff.ms",
+            config.producer.retry_interval.to_string(),
+        )
+        .create()
+}
+
+/// create the topic if it is not found in the metadata of the cluster,
+/// the partitions of an existing topic are not changed,
+/// because the keys would be moved to other partitions and lose the order
+pub(crate) async fn ensure_topic_exists(
+    config: &KafkaConfig,
+    topic_name: &str,
+) -> Result<(), KafkaError> {
+    // Create Kafka AdminClient
+    let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
+        .set("bootstrap.servers", config.hosts.join(","))
+        .set("socket.timeout.ms", config.connect_timeout.to_string())
+        .create()?;
+    let timeout = Duration::from_millis(config.connect_timeout as u64);
+
+    // query the metadata of all topics, querying a single topic may create it automatically
+    let metadata = admin_client.inner().fetch_metadata(None, timeout)?;
+    if let Some(topic) = metadata
+        .topics()
+        .iter()
+        .find(|topic| topic.name() == topic_name && topic.error().is_none())
+    {
+        let partitions = topic.partitions().len() as i32;
+        if partitions < config.partitions {
+            warn!(
+                "topic '{}' has {} partitions, less than {} in config",
+                topic_name, partitions, config.partitions
+            );
+        }
+        info!(
+            "topic '{}' exists with {} partitions",
+            topic_name, partitions
+        );
+        return Ok(());
+    }
+
+    let new_topics = [NewTopic {
+        name: topic_name,
+        num_partitions: config.partitions,
+        replication: TopicReplication::Fixed(config.replication),
+        config: vec![],
+    }];
+    let options = AdminOptions::new().operation_timeout(Some(timeout));
+    for result in admin_client.create_topics(&new_topics, &options).await? {
+        match result {
+            Ok(name) => info!("create topic '{}'", name),
+            // created by another instance at the same time
+            Err((name, RDKafkaErrorCode::TopicAlreadyExists)) => {
+                info!("topic '{}' already exists", name)
+            }
+            Err((_, code)) => return Err(KafkaError::AdminOp(code)),
+        }
+    }
+    Ok(())
+}
@@ -2,19 +2,29 @@ use abi::config::Config;
 use consumer::ConsumerService;
 use productor::ChatRpcService;
 
+pub mod bus;
 pub mod consumer;
+#[cfg(feature = "kafka")]
+pub mod dead_letter;
+#[cfg(feature = "kafka")]
+mod kafka;
+pub mod notification;
 pub mod productor;
 mod pusher;
 
 pub async fn start(config: &Config) {
+    // the rpc service and the consumer share the bus, the in-memory one is in this process
+    let bus = bus::message_bus(config).await;
+
     let cloned_conf = config.clone();
+    let cloned_bus = bus.clone();
     let pro = tokio::spawn(async move {
-        ChatRpcService::start(&cloned_conf).await;
+        ChatRpcService::start(&cloned_conf, cloned_bus).await;
     });
 
     let cloned_conf = config.clone();
     let con = tokio::spawn(async move {
-        ConsumerService::new(&cloned_conf)
+        ConsumerService::new(&cloned_conf, bus.as_ref())
             .await
             .consume()
             .await
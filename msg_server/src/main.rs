@@ -1,7 +1,8 @@
 use tracing::Level;
 
-use abi::config::Config;
+use abi::config::{Config, MessageBusKind};
 
+use msg_server::bus::message_bus;
 use msg_server::productor::ChatRpcService;
 
 #[tokio::main]
@@ -10,5 +11,11 @@ async fn main() {
         .with_max_level(Level::DEBUG)
         .init();
     let config = Config::load("config.yml").unwrap();
-    ChatRpcService::start(&config).await;
+    // nothing consumes the memory bus in this process, the publishing blocks once it is full
+    assert!(
+        config.message_bus != MessageBusKind::Memory,
+        "the memory message bus needs the consumer in the same process, start the message server component instead"
+    );
+    let bus = message_bus(&config).await;
+    ChatRpcService::start(&config, bus).await;
 }
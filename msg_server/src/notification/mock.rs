@@ -0,0 +1,44 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use tracing::info;
+
+use abi::errors::Error;
+use abi::message::PushToken;
+
+use crate::notification::{Notification, NotificationProvider};
+
+/// the max notifications kept by the mock provider
+const MAX_SENT: usize = 1000;
+
+/// keep the notifications in memory instead of sending them,
+/// for the tests and the development without push service
+#[derive(Debug, Default)]
+pub struct MockProvider {
+    sent: Mutex<Vec<(Vec<PushToken>, Notification)>>,
+}
+
+impl MockProvider {
+    /// the notifications sent, the oldest first
+    pub fn sent(&self) -> Vec<(Vec<PushToken>, Notification)> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for MockProvider {
+    async fn notify(&self, tokens: &[PushToken], notification: &Notification) -> Result<(), Error> {
+        info!(
+            "notify {} devices of user {}: {:?}",
+            tokens.len(),
+            notification.user_id,
+            notification
+        );
+        let mut sent = self.sent.lock().unwrap();
+        if sent.len() >= MAX_SENT {
+            sent.remove(0);
+        }
+        sent.push((tokens.to_vec(), notification.clone()));
+        Ok(())
+    }
+}
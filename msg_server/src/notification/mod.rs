@@ -0,0 +1,295 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, error};
+
+use abi::config::{Config, PushProviderKind};
+use abi::errors::Error;
+use abi::message::{Msg, PushToken};
+use cache::Cache;
+use db::DbRepo;
+
+mod mock;
+mod payload;
+mod webhook;
+
+pub use mock::MockProvider;
+pub use payload::Notification;
+
+/// deliver the notifications to the devices by the push service of the platforms
+#[async_trait]
+pub trait NotificationProvider: Send + Sync + Debug {
+    /// the tokens are the devices of the user of the notification
+    async fn notify(&self, tokens: &[PushToken], notification: &Notification) -> Result<(), Error>;
+}
+
+pub fn notification_provider(config: &Config) -> Option<Arc<dyn NotificationProvider>> {
+    match config.push.provider {
+        PushProviderKind::None => None,
+        PushProviderKind::Webhook => Some(Arc::new(webhook::WebhookProvider::new(&config.push))),
+        PushProviderKind::Mock => Some(Arc::new(MockProvider::default())),
+    }
+}
+
+/// the states read by the notifier
+#[async_trait]
+pub trait NotifyState: Send + Sync {
+    /// the users who have live connections
+    async fn online_users(&self, user_ids: &[String]) -> Result<HashSet<String>, Error>;
+
+    /// the users who muted the conversation
+    async fn muted_users(
+        &self,
+        conversation_id: &str,
+        user_ids: &[String],
+    ) -> Result<Vec<String>, Error>;
+
+    /// the tokens of all the devices of the users
+    async fn push_tokens(&self, user_ids: &[String]) -> Result<Vec<PushToken>, Error>;
+}
+
+/// the connections and the mute settings are in the cache, the push tokens are in the database
+struct StoredState {
+    cache: Arc<dyn Cache>,
+    db: Arc<DbRepo>,
+}
+
+#[async_trait]
+impl NotifyState for StoredState {
+    async fn online_users(&self, user_ids: &[String]) -> Result<HashSet<String>, Error> {
+        let gateways = self.cache.query_user_gateways(user_ids).await?;
+        Ok(gateways.into_keys().collect())
+    }
+
+    async fn muted_users(
+        &self,
+        conversation_id: &str,
+        user_ids: &[String],
+    ) -> Result<Vec<String>, Error> {
+        self.cache
+            .query_muted_users(conversation_id, user_ids)
+            .await
+    }
+
+    async fn push_tokens(&self, user_ids: &[String]) -> Result<Vec<PushToken>, Error> {
+        self.db.push.get_push_tokens(user_ids).await
+    }
+}
+
+/// notify the receivers who have no live connection,
+/// the online receivers get the messages from the gateways
+pub struct Notifier {
+    provider: Arc<dyn NotificationProvider>,
+    state: Arc<dyn NotifyState>,
+}
+
+impl Notifier {
+    pub fn new(
+        provider: Arc<dyn NotificationProvider>,
+        cache: Arc<dyn Cache>,
+        db: Arc<DbRepo>,
+    ) -> Self {
+        Self::with_state(provider, Arc::new(StoredState { cache, db }))
+    }
+
+    fn with_state(provider: Arc<dyn NotificationProvider>, state: Arc<dyn NotifyState>) -> Self {
+        Self { provider, state }
+    }
+
+    /// the messages with their receivers, the receivers of group message are the members
+    pub async fn notify(&self, messages: &[(Msg, Vec<String>)]) -> Result<(), Error> {
+        let receivers: Vec<String> = messages
+            .iter()
+            .flat_map(|(_, receivers)| receivers.iter().cloned())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let online = self.state.online_users(&receivers).await?;
+
+        let mut candidates = Vec::new();
+        // conversation id -> the offline receivers
+        let mut conversations: HashMap<&str, Vec<String>> = HashMap::new();
+        for (msg, receivers) in messages {
+            for receiver in receivers {
+                if online.contains(receiver) || receiver == &msg.send_id {
+                    continue;
+                }
+                candidates.push((receiver.clone(), msg));
+                conversations
+                    .entry(payload::conversation_id(msg))
+                    .or_default()
+                    .push(receiver.clone());
+            }
+        }
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        let mut muted = HashSet::new();
+        for (conversation_id, mut users) in conversations {
+            users.sort();
+            users.dedup();
+            for user_id in self.state.muted_users(conversation_id, &users).await? {
+                muted.insert((user_id, conversation_id.to_string()));
+            }
+        }
+
+        let notifications = payload::build(candidates, &muted);
+        let users: Vec<String> = notifications
+            .iter()
+            .map(|n| n.user_id.clone())
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        let mut tokens: HashMap<String, Vec<PushToken>> = HashMap::new();
+        for token in self.state.push_tokens(&users).await? {
+            tokens.entry(token.user_id.clone()).or_default().push(token);
+        }
+
+        // the notifications are best effort, the users get the messages by syncing
+        let tasks = notifications.iter().filter_map(|notification| {
+            let Some(tokens) = tokens.get(&notification.user_id) else {
+                debug!("user {} has no push token", notification.user_id);
+                return None;
+            };
+            Some(async move {
+                if let Err(e) = self.provider.notify(tokens, notification).await {
+                    error!("notify user {} error: {:?}", notification.user_id, e);
+                }
+            })
+        });
+        futures::future::join_all(tasks).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use abi::message::{ContentType, MsgType};
+
+    use super::*;
+
+    /// the states kept in memory
+    #[derive(Default)]
+    struct MemoryState {
+        online: HashSet<String>,
+        /// (user id, conversation id)
+        muted: HashSet<(String, String)>,
+        tokens: Vec<PushToken>,
+    }
+
+    #[async_trait]
+    impl NotifyState for MemoryState {
+        async fn online_users(&self, user_ids: &[String]) -> Result<HashSet<String>, Error> {
+            Ok(user_ids
+                .iter()
+                .filter(|id| self.online.contains(*id))
+                .cloned()
+                .collect())
+        }
+
+        async fn muted_users(
+            &self,
+            conversation_id: &str,
+            user_ids: &[String],
+        ) -> Result<Vec<String>, Error> {
+            Ok(user_ids
+                .iter()
+                .filter(|id| {
+                    self.muted
+                        .contains(&(id.to_string(), conversation_id.to_string()))
+                })
+                .cloned()
+                .collect())
+        }
+
+        async fn push_tokens(&self, user_ids: &[String]) -> Result<Vec<PushToken>, Error> {
+            Ok(self
+                .tokens
+                .iter()
+                .filter(|token| user_ids.contains(&token.user_id))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn token(user_id: &str, device_id: &str) -> PushToken {
+        PushToken {
+            user_id: user_id.to_string(),
+            device_id: device_id.to_string(),
+            token: format!("{}-{}", user_id, device_id),
+            ..Default::default()
+        }
+    }
+
+    fn text_msg(receiver_id: &str, msg_type: MsgType, text: &str) -> Msg {
+        Msg {
+            send_id: "alice".to_string(),
+            receiver_id: receiver_id.to_string(),
+            server_id: nanoid::nanoid!(),
+            msg_type: msg_type as i32,
+            content_type: ContentType::Text as i32,
+            content: text.as_bytes().to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn notifier_should_notify_offline_receivers() {
+        let state = MemoryState {
+            online: HashSet::from(["carol".to_string()]),
+            muted: HashSet::from([("dave".to_string(), "group".to_string())]),
+            tokens: vec![
+                token("bob", "phone"),
+                token("bob", "pad"),
+                token("carol", "phone"),
+                token("dave", "phone"),
+            ],
+        };
+        let provider = Arc::new(MockProvider::default());
+        let notifier = Notifier::with_state(provider.clone(), Arc::new(state));
+
+        let members = vec![
+            "alice".to_string(),
+            "bob".to_string(),
+            "dave".to_string(),
+            "erin".to_string(),
+        ];
+        let messages = vec![
+            (
+                text_msg("bob", MsgType::SingleMsg, "hello"),
+                vec!["bob".to_string()],
+            ),
+            (
+                text_msg("carol", MsgType::SingleMsg, "hello"),
+                vec!["carol".to_string()],
+            ),
+            (
+                text_msg("bob", MsgType::SingleMsg, "again"),
+                vec!["bob".to_string()],
+            ),
+            (text_msg("group", MsgType::GroupMsg, "hi all"), members),
+        ];
+        notifier.notify(&messages).await.unwrap();
+
+        // carol is online, dave muted the group, erin has no device and alice is the sender
+        let mut sent = provider.sent();
+        sent.sort_by(|a, b| a.1.conversation_id.cmp(&b.1.conversation_id));
+        assert_eq!(sent.len(), 2);
+
+        let (tokens, notification) = &sent[0];
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(notification.user_id, "bob");
+        assert_eq!(notification.conversation_id, "alice");
+        assert_eq!(notification.count, 2);
+        assert_eq!(notification.body, "2 new messages");
+
+        let (tokens, notification) = &sent[1];
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(notification.user_id, "bob");
+        assert_eq!(notification.conversation_id, "group");
+        assert_eq!(notification.body, "hi all");
+    }
+}
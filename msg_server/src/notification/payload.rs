@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use abi::message::{ContentType, Mention, Msg, MsgContent, MsgType};
+
+/// the max chars of the message preview
+const PREVIEW_LEN: usize = 100;
+
+/// the notification of the new messages of one conversation for a user
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Notification {
+    pub user_id: String,
+    /// the friend id of single chat or the group id
+    pub conversation_id: String,
+    /// the device replaces the previous notification with the same key
+    pub collapse_key: String,
+    pub title: String,
+    pub body: String,
+    /// the messages collapsed into this notification
+    pub count: usize,
+    /// the user is mentioned by one of the messages
+    pub mentioned: bool,
+    /// the server id of the latest message
+    pub server_id: String,
+    pub send_time: i64,
+}
+
+/// the conversation of the message from the receiver side
+pub(crate) fn conversation_id(msg: &Msg) -> &str {
+    if msg.msg_type == MsgType::GroupMsg as i32 {
+        &msg.receiver_id
+    } else {
+        &msg.send_id
+    }
+}
+
+/// collapse the messages of the same conversation into one notification for each user,
+/// the muted conversations are skipped unless the user is mentioned directly
+pub(crate) fn build(
+    candidates: Vec<(String, &Msg)>,
+    muted: &HashSet<(String, String)>,
+) -> Vec<Notification> {
+    let mut notifications: Vec<Notification> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+    for (user_id, msg) in candidates {
+        let key = (user_id, conversation_id(msg).to_string());
+        let (direct, all) = match mention_of(msg) {
+            Some(mention) => (mention.user_ids.contains(&key.0), mention.all),
+            None => (false, false),
+        };
+        if muted.contains(&key) && !direct {
+            continue;
+        }
+
+        if let Some(&i) = index.get(&key) {
+            let notification = &mut notifications[i];
+            notification.count += 1;
+            notification.mentioned |= direct || all;
+            notification.server_id.clone_from(&msg.server_id);
+            notification.send_time = msg.send_time;
+            notification.body = format!("{} new messages", notification.count);
+            continue;
+        }
+
+        index.insert(key.clone(), notifications.len());
+        let (user_id, conversation_id) = key;
+        let title = if msg.nickname.is_empty() {
+            msg.send_id.clone()
+        } else {
+            msg.nickname.clone()
+        };
+        notifications.push(Notification {
+            user_id,
+            collapse_key: conversation_id.clone(),
+            conversation_id,
+            title,
+            body: preview(msg),
+            count: 1,
+            mentioned: direct || all,
+            server_id: msg.server_id.clone(),
+            send_time: msg.send_time,
+        });
+    }
+
+    for notification in notifications.iter_mut().filter(|n| n.mentioned) {
+        notification.body = format!("[Mentioned] {}", notification.body);
+    }
+    notifications
+}
+
+/// the text message content is the bincode of MsgContent, or the plain text
+fn decode_content(msg: &Msg) -> Option<MsgContent> {
+    if msg.content_type != ContentType::Text as i32
+        && msg.content_type != ContentType::Default as i32
+    {
+        return None;
+    }
+    bincode::deserialize::<MsgContent>(&msg.content)
+        .ok()
+        .or_else(|| {
+            String::from_utf8(msg.content.clone())
+                .ok()
+                .map(|content| MsgContent {
+                    content,
+                    mention: None,
+                })
+        })
+}
+
+fn mention_of(msg: &Msg) -> Option<Mention> {
+    decode_content(msg).and_then(|content| content.mention)
+}
+
+fn preview(msg: &Msg) -> String {
+    let placeholder = match ContentType::try_from(msg.content_type).unwrap_or_default() {
+        ContentType::Default | ContentType::Text => {
+            return decode_content(msg)
+                .map(|content| content.content.chars().take(PREVIEW_LEN).collect())
+                .unwrap_or_default();
+        }
+        ContentType::Image => "[Image]",
+        ContentType::Video => "[Video]",
+        ContentType::Audio => "[Audio]",
+        ContentType::File => "[File]",
+        ContentType::Emoji => "[Emoji]",
+        ContentType::VideoCall => "[Video Call]",
+        ContentType::AudioCall => "[Audio Call]",
+        ContentType::Error => "",
+    };
+    placeholder.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_msg(send_id: &str, receiver_id: &str, msg_type: MsgType, content: MsgContent) -> Msg {
+        Msg {
+            send_id: send_id.to_string(),
+            receiver_id: receiver_id.to_string(),
+            server_id: nanoid::nanoid!(),
+            msg_type: msg_type as i32,
+            content_type: ContentType::Text as i32,
+            content: bincode::serialize(&content).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn content(text: &str, mention: Option<Mention>) -> MsgContent {
+        MsgContent {
+            content: text.to_string(),
+            mention,
+        }
+    }
+
+    #[test]
+    fn build_should_collapse_messages_of_conversation() {
+        let first = text_msg("alice", "bob", MsgType::SingleMsg, content("hello", None));
+        let second = text_msg("alice", "bob", MsgType::SingleMsg, content("again", None));
+        let other = text_msg("carol", "bob", MsgType::SingleMsg, content("hi", None));
+        let candidates = vec![
+            ("bob".to_string(), &first),
+            ("bob".to_string(), &second),
+            ("bob".to_string(), &other),
+        ];
+
+        let notifications = build(candidates, &HashSet::new());
+        assert_eq!(notifications.len(), 2);
+        assert_eq!(notifications[0].conversation_id, "alice");
+        assert_eq!(notifications[0].count, 2);
+        assert_eq!(notifications[0].body, "2 new messages");
+        assert_eq!(notifications[0].server_id, second.server_id);
+        assert_eq!(notifications[1].conversation_id, "carol");
+        assert_eq!(notifications[1].body, "hi");
+    }
+
+    #[test]
+    fn build_should_skip_muted_unless_mentioned() {
+        let mention = Mention {
+            all: false,
+            user_ids: vec!["bob".to_string()],
+        };
+        let plain = text_msg("alice", "group", MsgType::GroupMsg, content("hello", None));
+        let mentioned = text_msg(
+            "alice",
+            "group",
+            MsgType::GroupMsg,
+            content("look", Some(mention)),
+        );
+        let muted = HashSet::from([
+            ("bob".to_string(), "group".to_string()),
+            ("carol".to_string(), "group".to_string()),
+        ]);
+
+        let notifications = build(
+            vec![("bob".to_string(), &plain), ("carol".to_string(), &plain)],
+            &muted,
+        );
+        assert!(notifications.is_empty());
+
+        let notifications = build(
+            vec![
+                ("bob".to_string(), &mentioned),
+                ("carol".to_string(), &mentioned),
+            ],
+            &muted,
+        );
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0].user_id, "bob");
+        assert!(notifications[0].mentioned);
+        assert_eq!(notifications[0].body, "[Mentioned] look");
+    }
+
+    #[test]
+    fn preview_should_use_placeholder_for_media() {
+        let msg = Msg {
+            content_type: ContentType::Image as i32,
+            ..Default::default()
+        };
+        assert_eq!(preview(&msg), "[Image]");
+
+        let msg = Msg {
+            content_type: ContentType::Text as i32,
+            content: "plain text".as_bytes().to_vec(),
+            ..Default::default()
+        };
+        assert_eq!(preview(&msg), "plain text");
+    }
+}
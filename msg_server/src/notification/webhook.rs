@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use abi::config::PushConfig;
+use abi::errors::Error;
+use abi::message::PushToken;
+
+use crate::notification::{Notification, NotificationProvider};
+
+/// post the notifications to a http service,
+/// which forwards them to the push services of the platforms
+#[derive(Debug)]
+pub struct WebhookProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    tokens: &'a [PushToken],
+    notification: &'a Notification,
+}
+
+impl WebhookProvider {
+    pub fn new(config: &PushConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.timeout))
+            .build()
+            .expect("Webhook client creation failed");
+        Self {
+            client,
+            url: config.webhook_url.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationProvider for WebhookProvider {
+    async fn notify(&self, tokens: &[PushToken], notification: &Notification) -> Result<(), Error> {
+        let payload = WebhookPayload {
+            tokens,
+            notification,
+        };
+        self.client
+            .post(&self.url)
+            .json(&payload)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
@@ -1,56 +1,37 @@
-use std::time::Duration;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use nanoid::nanoid;
-use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
-use rdkafka::client::DefaultClientContext;
-use rdkafka::error::KafkaError;
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::ClientConfig;
 use synapse::health::{HealthServer, HealthService};
 use tonic::transport::Server;
 use tracing::{error, info};
 
 use abi::config::{Component, Config};
 use abi::message::chat_service_server::{ChatService, ChatServiceServer};
-use abi::message::{MsgResponse, MsgType, SendMsgRequest};
+use abi::message::{Msg, MsgResponse, MsgType, SendMsgRequest};
+
+use crate::bus::MessageBus;
 
 pub struct ChatRpcService {
-    kafka: FutureProducer,
-    topic: String,
+    bus: Arc<dyn MessageBus>,
 }
 
 impl ChatRpcService {
-    pub fn new(kafka: FutureProducer, topic: String) -> Self {
-        Self { kafka, topic }
+    pub fn new(bus: Arc<dyn MessageBus>) -> Self {
+        Self { bus }
     }
-    pub async fn start(config: &Config) {
-        let broker = config.kafka.hosts.join(",");
-        let producer: FutureProducer = ClientConfig::new()
-            .set("bootstrap.servers", &broker)
-            .set(
-                "message.timeout.ms",
-                config.kafka.producer.timeout.to_string(),
-            )
-            .set(
-                "socket.timeout.ms",
-                config.kafka.connect_timeout.to_string(),
-            )
-            .set("acks", config.kafka.producer.acks.clone())
-            // make sure the message is sent exactly once
-            .set("enable.idempotence", "true")
-            .set("retries", config.kafka.producer.max_retry.to_string())
-            .set(
-                "retry.backoff.ms",
-                config.kafka.producer.retry_interval.to_string(),
-            )
-            .create()
-            .expect("Producer creation error");
 
-        Self::ensure_topic_exists(&config.kafka.topic, &broker, config.kafka.connect_timeout)
-            .await
-            .expect("Topic creation error");
+    /// the receiver is the user or the group,
+    /// the messages without receiver like the presence are keyed by the sender
+    fn record_key(msg: &Msg) -> &str {
+        if msg.receiver_id.is_empty() {
+            &msg.send_id
+        } else {
+            &msg.receiver_id
+        }
+    }
 
+    pub async fn start(config: &Config, bus: Arc<dyn MessageBus>) {
         // register service
         utils::register_service(config, Component::MessageServer)
             .await
@@ -61,7 +42,7 @@ impl ChatRpcService {
         let health_service = HealthServer::new(HealthService::new());
         info!("<chat> rpc service health check started");
 
-        let chat_rpc = Self::new(producer, config.kafka.topic.clone());
+        let chat_rpc = Self::new(bus);
         let service = ChatServiceServer::new(chat_rpc);
         info!(
             "<chat> rpc service started at {}",
@@ -75,44 +56,6 @@ impl ChatRpcService {
             .await
             .unwrap();
     }
-
-    async fn ensure_topic_exists(
-        topic_name: &str,
-        brokers: &str,
-        timeout: u16,
-    ) -> Result<(), KafkaError> {
-        // Create Kafka AdminClient
-        let admin_client: AdminClient<DefaultClientContext> = ClientConfig::new()
-            .set("bootstrap.servers", brokers)
-            .set("socket.timeout.ms", timeout.to_string())
-            .create()?;
-
-        // create topic
-        let new_topics = [NewTopic {
-            name: topic_name,
-            num_partitions: 1,
-            replication: TopicReplication::Fixed(1),
-            config: vec![],
-        }];
-
-        // fixme not find the way to check topic exist
-        // so just create it and judge the error,
-        // but don't find the error type for topic exist
-        // and this way below can work well.
-        let options = AdminOptions::new();
-        admin_client.create_topics(&new_topics, &options).await?;
-        match admin_client.create_topics(&new_topics, &options).await {
-            Ok(_) => {
-                info!("Topic not exist; create '{}' ", topic_name);
-                Ok(())
-            }
-            Err(KafkaError::AdminOpCreation(_)) => {
-                println!("Topic '{}' already exists.", topic_name);
-                Ok(())
-            }
-            Err(err) => Err(err),
-        }
-    }
 }
 
 #[async_trait]
@@ -131,25 +74,23 @@ impl ChatService for ChatRpcService {
         // generate msg id
         if !(msg.msg_type == MsgType::GroupDismissOrExitReceived as i32
             || msg.msg_type == MsgType::GroupInvitationReceived as i32
-            || msg.msg_type == MsgType::FriendshipReceived as i32)
+            || msg.msg_type == MsgType::FriendshipReceived as i32
+            || msg.msg_type == MsgType::Delivered as i32)
         {
             msg.server_id = nanoid!();
         }
         msg.send_time = chrono::Utc::now().timestamp_millis();
 
-        // send msg to kafka
+        // send msg to the message bus
         let payload = serde_json::to_string(&msg).unwrap();
-        // let kafka generate key, then we need set FutureRecord<String, type>
-        let record: FutureRecord<String, String> = FutureRecord::to(&self.topic).payload(&payload);
-
-        info!("send msg to kafka: {:?}", record);
-        let err = match self.kafka.send(record, Duration::from_secs(0)).await {
-            Ok(_) => String::new(),
-            Err((err, msg)) => {
-                error!(
-                    "send msg to kafka error: {:?}; owned message: {:?}",
-                    err, msg
-                );
+        // the messages of the same receiver go to the same partition and keep the order
+        let key = Self::record_key(&msg);
+
+        info!("publish msg: {}", payload);
+        let err = match self.bus.publish(key, payload).await {
+            Ok(()) => String::new(),
+            Err(err) => {
+                error!("publish msg error: {:?}; message: {:?}", err, msg);
                 err.to_string()
             }
         };
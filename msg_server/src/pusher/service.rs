@@ -1,16 +1,18 @@
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use abi::errors::Error;
 use async_trait::async_trait;
+use cache::Cache;
 use dashmap::DashMap;
 use synapse::service::client::ServiceClient;
 use synapse::service::Service;
 use tokio::sync::mpsc;
 use tonic::transport::{Channel, Endpoint};
 use tower::discover::Change;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 use abi::config::Config;
 use abi::message::msg_service_client::MsgServiceClient;
@@ -23,6 +25,8 @@ pub struct PusherService {
     ws_rpc_list: Arc<DashMap<SocketAddr, MsgServiceClient<Channel>>>,
     service_center: ServiceClient,
     sub_svr_name: String,
+    /// the registry of which gateway holds the user's connection
+    cache: Arc<dyn Cache>,
 }
 
 impl PusherService {
@@ -65,10 +69,12 @@ impl PusherService {
             .build()
             .await
             .unwrap();
+        let cache = cache::cache(config);
         Self {
             ws_rpc_list,
             service_center,
             sub_svr_name,
+            cache,
         }
     }
 
@@ -101,15 +107,10 @@ impl PusherService {
             self.ws_rpc_list.insert(socket, ws);
         }
     }
-}
 
-#[async_trait]
-impl Pusher for PusherService {
-    async fn push_single_msg(&self, request: Msg) -> Result<(), Error> {
-        debug!("push msg request: {:?}", request);
-
-        let ws_rpc = self.ws_rpc_list.clone();
-        if ws_rpc.is_empty() {
+    /// query the ws services from service center if there is no available service
+    async fn ensure_ws_rpc_list(&self) -> Result<(), Error> {
+        if self.ws_rpc_list.is_empty() {
             let mut client = self.service_center.clone();
             let list = client
                 .query_with_name(self.sub_svr_name.clone())
@@ -117,17 +118,124 @@ impl Pusher for PusherService {
                 .map_err(|e| Error::internal_with_details(e.to_string()))?;
             self.handle_sub_services(list).await;
         }
+        Ok(())
+    }
+
+    /// query the gateways which hold the users' connections,
+    /// and group the users by gateway.
+    /// return None if the registry is not available or any gateway is unknown to the service list,
+    /// in this case the caller should send the message to all gateways
+    async fn route(&self, user_ids: &[String]) -> Option<HashMap<SocketAddr, Vec<String>>> {
+        let gateways = match self.cache.query_user_gateways(user_ids).await {
+            Ok(gateways) => gateways,
+            Err(err) => {
+                error!("query user gateways error: {:?}", err);
+                return None;
+            }
+        };
+
+        let mut routes: HashMap<SocketAddr, Vec<String>> = HashMap::new();
+        // the users mostly share a few gateways, resolve each of them once
+        let mut resolved: HashMap<String, Option<SocketAddr>> = HashMap::new();
+        for (user_id, list) in gateways {
+            for gateway in list {
+                let addr = match resolved.get(&gateway) {
+                    Some(addr) => *addr,
+                    None => {
+                        let addr = self.resolve(&gateway).await;
+                        resolved.insert(gateway.clone(), addr);
+                        addr
+                    }
+                };
+                match addr {
+                    Some(addr) if self.ws_rpc_list.contains_key(&addr) => {
+                        routes.entry(addr).or_default().push(user_id.clone());
+                    }
+                    // the service list may be behind the registry, do not drop the message
+                    _ => {
+                        warn!("gateway {} is not in the service list, fan out", gateway);
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(routes)
+    }
+
+    /// the gateway registers the configured host, which may be a hostname like a docker service,
+    /// resolve it to the address of the gateway client
+    async fn resolve(&self, gateway: &str) -> Option<SocketAddr> {
+        let addrs: Vec<SocketAddr> = match tokio::net::lookup_host(gateway).await {
+            Ok(addrs) => addrs.collect(),
+            Err(err) => {
+                error!("resolve gateway address {} error: {:?}", gateway, err);
+                return None;
+            }
+        };
+        let addr = addrs
+            .iter()
+            .find(|addr| self.ws_rpc_list.contains_key(addr))
+            .or(addrs.first())
+            .copied();
+        if addr.is_none() {
+            error!("gateway address {} is resolved to nothing", gateway);
+        }
+        addr
+    }
+
+    /// get the rpc clients of the given gateways,
+    /// all the gateways will be returned if the addresses is None
+    fn ws_clients(
+        &self,
+        addrs: Option<Vec<SocketAddr>>,
+    ) -> Vec<(SocketAddr, MsgServiceClient<Channel>)> {
+        match addrs {
+            None => self
+                .ws_rpc_list
+                .iter()
+                .map(|v| (*v.key(), v.value().clone()))
+                .collect(),
+            Some(addrs) => addrs
+                .into_iter()
+                .filter_map(|addr| match self.ws_rpc_list.get(&addr) {
+                    Some(client) => Some((addr, client.clone())),
+                    None => {
+                        warn!("gateway {} is not available", addr);
+                        None
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl Pusher for PusherService {
+    async fn push_single_msg(&self, request: Msg) -> Result<(), Error> {
+        debug!("push msg request: {:?}", request);
+
+        self.ensure_ws_rpc_list().await?;
+
+        // the receiver and the sender's other devices
+        let users = [request.receiver_id.clone(), request.send_id.clone()];
+        let addrs = self
+            .route(&users)
+            .await
+            .map(|routes| routes.into_keys().collect());
+        let clients = self.ws_clients(addrs);
+        if clients.is_empty() {
+            debug!("user {} is offline", request.receiver_id);
+            return Ok(());
+        }
 
         let request = SendMsgRequest {
             message: Some(request),
         };
-        let (tx, mut rx) = mpsc::channel(ws_rpc.len());
+        let (tx, mut rx) = mpsc::channel(clients.len());
 
         // send message to ws with asynchronous way
-        for v in ws_rpc.iter() {
+        for (service_id, mut v) in clients {
             let tx = tx.clone();
-            let service_id = *v.key();
-            let mut v = v.clone();
             let request = request.clone();
             tokio::spawn(async move {
                 if let Err(err) = v.send_msg_to_user(request).await {
@@ -141,7 +249,7 @@ impl Pusher for PusherService {
 
         // todo need to update client list; and need to handle error
         while let Some((service_id, err)) = rx.recv().await {
-            ws_rpc.remove(&service_id);
+            self.ws_rpc_list.remove(&service_id);
             error!("push msg to {} failed: {}", service_id, err);
         }
         Ok(())
@@ -149,28 +257,54 @@ impl Pusher for PusherService {
 
     async fn push_group_msg(&self, msg: Msg, members: Vec<GroupMemSeq>) -> Result<(), Error> {
         debug!("push group msg request: {:?}, {:?}", msg, members);
-        // extract request
-        let ws_rpc = self.ws_rpc_list.clone();
-        if ws_rpc.is_empty() {
-            let mut client = self.service_center.clone();
-            let list = client
-                .query_with_name(self.sub_svr_name.clone())
-                .await
-                .map_err(|e| Error::internal_with_details(e.to_string()))?;
-            self.handle_sub_services(list).await;
-        }
 
-        let request = SendGroupMsgRequest {
-            message: Some(msg),
-            members,
+        self.ensure_ws_rpc_list().await?;
+
+        // batch the members by gateway
+        let mut users: Vec<String> = members.iter().map(|m| m.mem_id.clone()).collect();
+        users.push(msg.send_id.clone());
+        let batches = match self.route(&users).await {
+            Some(routes) => {
+                let members: HashMap<String, GroupMemSeq> =
+                    members.into_iter().map(|m| (m.mem_id.clone(), m)).collect();
+                let mut batches = HashMap::with_capacity(routes.len());
+                for (addr, ids) in routes {
+                    // the gateway holds the sender only still need the message,
+                    // because it will send the message to the sender's other devices
+                    let batch: Vec<GroupMemSeq> = ids
+                        .iter()
+                        .filter_map(|id| members.get(id).cloned())
+                        .collect();
+                    batches.insert(addr, batch);
+                }
+                let addrs = batches.keys().copied().collect();
+                self.ws_clients(Some(addrs))
+                    .into_iter()
+                    .map(|(addr, client)| {
+                        let batch = batches.remove(&addr).unwrap_or_default();
+                        (addr, client, batch)
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => self
+                .ws_clients(None)
+                .into_iter()
+                .map(|(addr, client)| (addr, client, members.clone()))
+                .collect(),
         };
-        let (tx, mut rx) = mpsc::channel(ws_rpc.len());
+        if batches.is_empty() {
+            debug!("group {} members are offline", msg.receiver_id);
+            return Ok(());
+        }
+
+        let (tx, mut rx) = mpsc::channel(batches.len());
         // send message to ws with asynchronous way
-        for v in ws_rpc.iter() {
+        for (service_id, mut v, members) in batches {
             let tx = tx.clone();
-            let service_id = *v.key();
-            let mut v = v.clone();
-            let request = request.clone();
+            let request = SendGroupMsgRequest {
+                message: Some(msg.clone()),
+                members,
+            };
             tokio::spawn(async move {
                 match v.send_group_msg_to_user(request).await {
                     Ok(_) => {
@@ -186,7 +320,7 @@ impl Pusher for PusherService {
         drop(tx);
         // todo need to update client list
         while let Some(Err((service_id, err))) = rx.recv().await {
-            ws_rpc.remove(&service_id);
+            self.ws_rpc_list.remove(&service_id);
             error!("push msg to {} failed: {}", service_id, err);
         }
         Ok(())
@@ -8,6 +8,7 @@ use argon2::password_hash::rand_core::OsRng;
 use argon2::{Argon2, PasswordHasher};
 use async_trait::async_trait;
 use client_factory::ClientFactory;
+use subtle::ConstantTimeEq;
 use synapse::health::HealthCheck;
 use synapse::service::client::ServiceClient;
 use synapse::service::{
@@ -240,6 +241,19 @@ pub async fn start_register_center(config: &Config) {
 }
 
 pub async fn register_service(config: &Config, com: Component) -> Result<(), Error> {
+    update_service(config, com, ServiceStatus::Up).await
+}
+
+/// mark the service as down, the subscribers will remove it from their lists
+pub async fn deregister_service(config: &Config, com: Component) -> Result<(), Error> {
+    update_service(config, com, ServiceStatus::Down).await
+}
+
+async fn update_service(
+    config: &Config,
+    com: Component,
+    status: ServiceStatus,
+) -> Result<(), Error> {
     // register service to service register center
     let addr = format!(
         "{}://{}:{}",
@@ -291,13 +305,61 @@ pub async fn register_service(config: &Config, com: Component) -> Result<(), Err
         version: "".to_string(),
         metadata: Default::default(),
         health_check,
-        status: 0,
+        status: status as i32,
         scheme,
     };
-    client.register_service(service).await.unwrap();
+    client.register_service(service).await?;
     Ok(())
 }
 
+/// the header of http request and the metadata key of rpc request which carries the token
+pub const AUTHORIZATION: &str = "authorization";
+
+/// the token of the `Bearer <token>` authorization value
+pub fn bearer_token(authorization: &str) -> Option<&str> {
+    authorization
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// compare the token with the admin token in constant time,
+/// the admin api is disabled if the admin token is not set or empty
+pub fn check_admin_token(admin_token: Option<&str>, token: Option<&str>) -> bool {
+    match (admin_token, token) {
+        (Some(admin_token), Some(token)) if !admin_token.is_empty() => {
+            admin_token.as_bytes().ct_eq(token.as_bytes()).into()
+        }
+        _ => false,
+    }
+}
+
+/// check the admin token carried by the rpc request
+pub fn check_admin_request<T>(
+    admin_token: Option<&str>,
+    request: &tonic::Request<T>,
+) -> Result<(), Error> {
+    let token = request
+        .metadata()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(bearer_token);
+    if check_admin_token(admin_token, token) {
+        Ok(())
+    } else {
+        Err(Error::unauthorized_with_details("admin token is invalid"))
+    }
+}
+
+/// build the rpc request of the admin api, which carries the admin token
+pub fn admin_request<T>(message: T, admin_token: Option<&str>) -> tonic::Request<T> {
+    let mut request = tonic::Request::new(message);
+    if let Some(Ok(value)) = admin_token.map(|token| format!("Bearer {}", token).parse()) {
+        request.metadata_mut().insert(AUTHORIZATION, value);
+    }
+    request
+}
+
 pub async fn get_rpc_client<T: ClientFactory>(
     config: &Config,
     service_name: String,
@@ -323,4 +385,24 @@ mod tests {
                 .is_ok()
         );
     }
+
+    #[test]
+    fn test_admin_token() {
+        assert_eq!(bearer_token("Bearer secret"), Some("secret"));
+        assert_eq!(bearer_token("Bearer "), None);
+        assert_eq!(bearer_token("Basic secret"), None);
+
+        assert!(check_admin_token(Some("secret"), Some("secret")));
+        assert!(!check_admin_token(Some("secret"), Some("secreT")));
+        assert!(!check_admin_token(Some("secret"), None));
+        // disabled if the admin token is not set
+        assert!(!check_admin_token(Some(""), Some("")));
+        assert!(!check_admin_token(None, Some("secret")));
+
+        let request = admin_request((), Some("secret"));
+        assert!(check_admin_request(Some("secret"), &request).is_ok());
+        assert!(check_admin_request(Some("other"), &request).is_err());
+        let request = admin_request((), None);
+        assert!(check_admin_request(Some("secret"), &request).is_err());
+    }
 }